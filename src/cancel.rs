@@ -0,0 +1,142 @@
+//! ## cancel module
+//!
+//! Cooperative cancellation of the tasks built with `TaskBuild::timeout()`.
+
+use std::{
+    cell::RefCell,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    task::Waker,
+    thread,
+    time::Duration,
+};
+
+use crate::{task::TaskId, timer};
+
+#[derive(Default)]
+struct State {
+    cancelled: AtomicBool,
+    done: AtomicBool,
+    /// wakes the awaited task pending at the timeout, to be dropped rather than polled on
+    waker: Mutex<Option<Waker>>,
+}
+
+/// A token telling the running task whether it should stop.
+///
+/// A task built with `timeout()` gets a token cancelled once the timeout expires,
+/// the task body is expected to poll it and return early. The result of a timed out task
+/// is never passed to its target condaddrs.
+///
+/// # Example:
+/// ```rust
+/// # use std::time::Duration;
+/// # use taskorch::{CancelToken, TaskBuildNew as _};
+/// let task = (||{
+///     let token = CancelToken::current();
+///     while !token.is_cancelled() {
+///         // do a piece of the work
+///         # break;
+///     }
+/// }).into_task().timeout(Duration::from_secs(1));
+/// ```
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<State>);
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+impl CancelToken {
+    /// The token of the task running on the current thread.
+    ///
+    /// Outside a task, or if the task has no timeout, the token is never cancelled.
+    pub fn current()->CancelToken {
+        CURRENT.with_borrow(|token|token.clone()).unwrap_or_default()
+    }
+
+    /// Whether the task should stop as soon as possible.
+    pub fn is_cancelled(&self)->bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// Watches a running task with a timeout, from the start of the run until it is dropped,
+/// kept in an awaited task across its polls.
+///
+/// The token is cancelled once the timeout expires,
+/// and the task is reported if it is still running twice as long as the timeout.
+pub(crate) struct Watch(CancelToken);
+
+impl Watch {
+    pub(crate) fn start(_taskid:TaskId, timeout:Duration)->Self {
+        let token = CancelToken::default();
+
+        let state = token.0.clone();
+        timer::schedule(timeout, move||{
+            if !state.done.load(Ordering::Acquire) {
+                state.cancelled.store(true, Ordering::Release);
+                warn!(task_id=_taskid; "task#{_taskid:?} exceeded its timeout {timeout:?} and is cancelled.");
+                if let Some(waker) = state.waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        });
+
+        let state = token.0.clone();
+        let _thread = thread::current();
+        timer::schedule(timeout.saturating_mul(2), move||{
+            if !state.done.load(Ordering::Acquire) {
+                error!(task_id=_taskid; "watchdog: task#{_taskid:?} is still running on {:?} {:?}, {:?} after its timeout {timeout:?}.",
+                    _thread.name().unwrap_or(""), _thread.id(), timeout);
+            }
+        });
+        Self(token)
+    }
+
+    /// Makes the token the current one of the thread, until the guard is dropped.
+    pub(crate) fn enter(&self)->Entered {
+        CURRENT.set(Some(self.0.clone()));
+        Entered
+    }
+
+    /// Wakes the pending task by `waker` once the timeout expires.
+    pub(crate) fn wake_on_timeout(&self, waker:&Waker) {
+        *self.0.0.waker.lock().unwrap() = Some(waker.clone());
+    }
+
+    pub(crate) fn token(&self)->CancelToken {
+        self.0.clone()
+    }
+
+    /// Whether the task has run beyond its timeout.
+    pub(crate) fn timed_out(&self)->bool {
+        self.0.is_cancelled()
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.0.0.done.store(true, Ordering::Release);
+    }
+}
+
+/// The token of a watch is the current one of the thread, until dropped.
+pub(crate) struct Entered;
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.set(None);
+    }
+}
+
+#[test]
+fn test_watch() {
+    assert!(!CancelToken::current().is_cancelled());
+    let watch = Watch::start(TaskId::from(1), Duration::from_millis(5));
+    let entered = watch.enter();
+    let token = CancelToken::current();
+    assert!(!token.is_cancelled());
+    thread::sleep(Duration::from_millis(50));
+    assert!(token.is_cancelled() && watch.timed_out());
+    drop(entered);
+    assert!(!CancelToken::current().is_cancelled());
+}
@@ -0,0 +1,555 @@
+//! ## checkpoint module
+//!
+//! Saves the tasks of a pool to a snapshot and restores them into another pool, e.g. after a crash,
+//! see `Pool::checkpoint()` and `Pool::restore()`.
+//!
+//! A closure can not be saved, so each task is saved by the name its body is registered by
+//! in a `TaskRegistry`, see `TaskBuild::named()`, and each filled cond by its value encoded by `CondCodec`.
+
+use std::{
+    any::type_name,
+    collections::HashMap,
+    fmt::{self, Debug},
+    io::{self, BufRead, Write},
+    num::NonZeroUsize,
+};
+
+use crate::{
+    curry::{CallParam, Currier},
+    queue::C1map,
+    submitter::{TaskError, TaskSubmitter},
+    task::{CondAddr, Kind, NullMapFn, Pi, Task, TaskAttr, TaskBuild, TaskCurrier, TaskId, TaskMap},
+    Queue,
+};
+
+const HEADER: &str = "taskorch-checkpoint 1";
+
+/// Encodes a cond value into a text saved by `Pool::checkpoint()`, and decodes it back by `Pool::restore()`.
+///
+/// Implemented for the primitive types, `String` and `Option` of them.
+///
+/// # Example:
+/// ```rust
+/// # use taskorch::CondCodec;
+/// #[derive(Clone, Debug)]
+/// struct Point { x:i32, y:i32 }
+///
+/// impl CondCodec for Point {
+///     fn encode(&self)->String {
+///         format!("{},{}", self.x, self.y)
+///     }
+///     fn decode(text:&str)->Option<Self> {
+///         let (x,y) = text.split_once(',')?;
+///         Some(Point { x: x.parse().ok()?, y: y.parse().ok()? })
+///     }
+/// }
+/// ```
+pub trait CondCodec: Sized {
+    fn encode(&self)->String;
+    /// `None` if the text is not a valid encoding
+    fn decode(text:&str)->Option<Self>;
+}
+
+macro_rules! impl_cond_codec {
+    ($($T:ty),+) => {
+        $(
+        impl CondCodec for $T {
+            fn encode(&self)->String {
+                self.to_string()
+            }
+            fn decode(text:&str)->Option<Self> {
+                text.parse().ok()
+            }
+        }
+        )+
+    };
+}
+
+impl_cond_codec!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, bool, char, String);
+
+/// `None` is encoded as `-`, `Some(v)` as `+` followed by the encoded `v`.
+impl<T:CondCodec> CondCodec for Option<T> {
+    fn encode(&self)->String {
+        match self {
+            Some(v) => format!("+{}", v.encode()),
+            None => "-".to_string(),
+        }
+    }
+    fn decode(text:&str)->Option<Self> {
+        match text {
+            "-" => Some(None),
+            _ => T::decode(text.strip_prefix('+')?).map(Some),
+        }
+    }
+}
+
+/// The failures of saving or restoring the tasks of a pool.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// the task is not named, see `TaskBuild::named()`
+    Unnamed(TaskId),
+    /// no task body is registered by the name
+    Unregistered(String),
+    /// the conds of the task are not those of the body registered by its name
+    Mismatch { taskid:TaskId, name:String },
+    /// the task distributes its result by `fan_tuple_with()`, which can not be saved
+    FansOut(TaskId),
+    /// the snapshot is not of a known format or version, with its header
+    Version(String),
+    /// the line of the snapshot is malformed
+    Parse { line:usize, reason:&'static str },
+    /// the value of the cond on the line of the snapshot can not be decoded
+    Decode { line:usize, pi:Pi, type_name:&'static str },
+    /// the pool has no queue with the id
+    NoQueue(usize),
+    /// the restored task can not be submitted
+    Submit(TaskError),
+    /// the count of the tasks put aside, which can not be saved:
+    /// delayed by a retry or a rate limit, parked for a resource, or pending as awaited
+    Delayed(usize),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {e}"),
+            Self::Unnamed(taskid) => write!(f, "task#{taskid:?} is not named"),
+            Self::Unregistered(name) => write!(f, "no task is registered by the name {name:?}"),
+            Self::Mismatch { taskid, name } => write!(f, "the conds of task#{taskid:?} are not those of {name:?}"),
+            Self::FansOut(taskid) => write!(f, "task#{taskid:?} fans out its result, which can not be saved"),
+            Self::Version(header) => write!(f, "unknown snapshot header {header:?}"),
+            Self::Parse { line, reason } => write!(f, "line {line}: {reason}"),
+            Self::Decode { line, pi, type_name } => write!(f, "line {line}: cond#{} is not a valid <{type_name}>", pi.0),
+            Self::NoQueue(qid) => write!(f, "no Q#{qid} in the pool"),
+            Self::Submit(e) => write!(f, "failed to submit: {e:?}"),
+            Self::Delayed(n) => write!(f, "{n} tasks are put aside and can not be saved"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e:io::Error)->Self {
+        Self::Io(e)
+    }
+}
+
+/// The task bodies by their names, to save and restore the tasks of a pool.
+///
+/// # Example:
+/// ```rust
+/// # use taskorch::TaskRegistry;
+/// let mut registry = TaskRegistry::new();
+/// registry
+///     .register("parse", |text:String|text.parse::<i32>().unwrap_or(0))
+///     .register("sum", |a:i32,b:i32|a+b);
+/// ```
+///
+/// # Snapshot format, version 1
+///
+/// A text of lines, the first one is the header `taskorch-checkpoint 1`,
+/// each other line is a task with its fields separated by a tab:
+///
+/// ```text
+/// <state> <qid> <taskid> <name> <kind> <next> <cond#0> <cond#1> ...
+/// ```
+///
+/// * `state`: `waiting` for a conditional task waiting for its conds, `queued` for a task ready in a queue
+/// * `qid`: the id of the queue the task is submitted to
+/// * `taskid`: the id of the task, `0` for an independent task without an explicit id
+/// * `name`: the name the body of the task is registered by
+/// * `kind`: `normal` or `exit`
+/// * `next`: the cond the result goes to as `<taskid>:<pi>`, see `TaskBuild::to()`, or `-` if none
+/// * `cond#i`: `-` if not filled, or else `=` followed by the encoded value
+///
+/// The name and the values escape the backslash, the tab, the newline and the carriage return
+/// as `\\`, `\t`, `\n` and `\r`. The empty lines and those starting with `#` are skipped.
+/// The waiting tasks come first, in the order of their ids, then the queued tasks, queue by queue,
+/// in the order they are going to be popped.
+#[derive(Default)]
+pub struct TaskRegistry {
+    bodies: HashMap<&'static str,Body>,
+}
+
+impl TaskRegistry {
+    pub fn new()->Self {
+        Self::default()
+    }
+
+    /// Registers the task body by the name, its conds must implement `CondCodec`.
+    ///
+    /// A body registered by the same name before is replaced.
+    #[allow(private_bounds)]
+    pub fn register<Args>(&mut self, name:&'static str, body:impl Register<Args>)->&mut Self {
+        if self.bodies.insert(name, body.body(name)).is_some() {
+            warn!("the task body registered by {name:?} is replaced.");
+        }
+        self
+    }
+
+    fn get(&self, name:&str)->Result<&Body,CheckpointError> {
+        self.bodies.get(name).ok_or_else(||CheckpointError::Unregistered(name.to_string()))
+    }
+
+    /// encodes the cond#`i` of the task named, None if not registered or not filled.
+    pub(crate) fn encode(&self, name:&str, param:&dyn CallParam, i:usize)->Option<String> {
+        (self.bodies.get(name)?.encode)(param, i)
+    }
+
+    /// decodes the text into the cond#`i` of the task named, false if not registered or not valid.
+    pub(crate) fn decode(&self, name:&str, param:&mut dyn CallParam, i:usize, text:&str)->bool {
+        self.bodies.get(name).is_some_and(|body|(body.decode)(param, i, text))
+    }
+}
+
+type Encode = fn(&dyn CallParam, usize)->Option<String>;
+type Decode = fn(&mut dyn CallParam, usize, &str)->bool;
+type Submit = Box<dyn FnOnce(&TaskSubmitter)->Result<TaskId,TaskError>>;
+type Prepare = Box<dyn Fn(&Record)->Result<Submit,CheckpointError> + Send + Sync>;
+
+/// A task body registered, with how its conds are saved and how it is rebuilt.
+pub(crate) struct Body {
+    /// the type names of the conds
+    conds: Vec<&'static str>,
+    encode: Encode,
+    decode: Decode,
+    /// decodes the conds of the record into a task ready to submit
+    prepare: Prepare,
+}
+
+pub(crate) trait Register<Args> {
+    fn body(self, name:&'static str)->Body;
+}
+
+macro_rules! impl_register {
+    ($($i:tt $P:ident),*) => {
+        impl<F, $($P,)* R> Register<($($P,)*)> for F
+        where
+            F: Fn($($P),*)->R + Clone + Send + Sync + 'static,
+            $($P: CondCodec + Clone + Debug + Send + 'static,)*
+            R: Debug + Send + 'static,
+        {
+            fn body(self, name:&'static str)->Body {
+                let encode: Encode = |_param, i|match i {
+                    $($i => _param.get(i)?.downcast_ref::<$P>().map(CondCodec::encode),)*
+                    _ => None,
+                };
+                let decode: Decode = |_param, i, _text|match i {
+                    $($i => <$P as CondCodec>::decode(_text).is_some_and(|value|_param.set(i, &value)),)*
+                    _ => false,
+                };
+                let prepare = move|record:&Record|->Result<Submit,CheckpointError> {
+                    #[allow(unused_mut)]
+                    let mut currier = Currier::<F,($($P,)*),R>::from(self.clone());
+                    $(
+                    if let Some(text) = &record.conds[$i] {
+                        let value = <$P as CondCodec>::decode(text)
+                            .ok_or(CheckpointError::Decode { line: record.line, pi: Pi($i), type_name: type_name::<$P>() })?;
+                        currier.set($i, &value);
+                    }
+                    )*
+                    let task = TaskCurrier {
+                        currier,
+                        id: record.taskid,
+                        kind: record.kind,
+                        attr: TaskAttr { name: Some(name), ..TaskAttr::default() },
+                    };
+                    let map: TaskMap<NullMapFn<R>,()> = match record.next {
+                        Some(ca) => TaskMap::To(ca),
+                        None => TaskMap::None,
+                    };
+                    Ok(Box::new(move|submitter:&TaskSubmitter|submitter.submit(TaskBuild(task, map))))
+                };
+                Body { conds: vec![$(type_name::<$P>()),*], encode, decode, prepare: Box::new(prepare) }
+            }
+        }
+    };
+}
+
+impl_register!();
+impl_register!(0 P1);
+impl_register!(0 P1, 1 P2);
+impl_register!(0 P1, 1 P2, 2 P3);
+impl_register!(0 P1, 1 P2, 2 P3, 3 P4);
+impl_register!(0 P1, 1 P2, 2 P3, 3 P4, 4 P5);
+impl_register!(0 P1, 1 P2, 2 P3, 3 P4, 4 P5, 5 P6);
+impl_register!(0 P1, 1 P2, 2 P3, 3 P4, 4 P5, 5 P6, 6 P7);
+impl_register!(0 P1, 1 P2, 2 P3, 3 P4, 4 P5, 5 P6, 6 P7, 7 P8);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Waiting,
+    Queued,
+}
+
+/// A task saved, a line of the snapshot.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Record {
+    state: State,
+    qid: usize,
+    taskid: TaskId,
+    name: String,
+    kind: Kind,
+    next: Option<CondAddr>,
+    /// the encoded values of the conds, `None` if not filled
+    conds: Vec<Option<String>>,
+    /// the line number in the snapshot, for the errors
+    line: usize,
+}
+
+impl Record {
+    fn of(registry:&TaskRegistry, state:State, qid:usize, task:&mut (dyn Task+Send))->Result<Self,CheckpointError> {
+        let taskid = task.id();
+        let kind = task.kind();
+        let attr = task.attr_mut();
+        if attr.fans_out {
+            return Err(CheckpointError::FansOut(taskid));
+        }
+        let next = attr.next;
+        let name = attr.name.ok_or(CheckpointError::Unnamed(taskid))?;
+        let body = registry.get(name)?;
+        let mismatch = ||CheckpointError::Mismatch { taskid, name: name.to_string() };
+        let conds = match task.as_param_mut() {
+            Some(param) => {
+                let param: &dyn CallParam = param;
+                if param.arity() != body.conds.len() || (0..param.arity()).any(|i|param.typename(i) != body.conds[i]) {
+                    return Err(mismatch());
+                }
+                (0..param.arity())
+                    .map(|i|match param.is_set(i) {
+                        true => (body.encode)(param, i).map(Some).ok_or_else(mismatch),
+                        false => Ok(None),
+                    })
+                    .collect::<Result<_,_>>()?
+            }
+            None if body.conds.is_empty() => Vec::new(),
+            None => return Err(mismatch()),
+        };
+        Ok(Self { state, qid, taskid, name: name.to_string(), kind, next, conds, line: 0 })
+    }
+
+    fn write(&self, w:&mut impl Write)->io::Result<()> {
+        let state = match self.state {
+            State::Waiting => "waiting",
+            State::Queued => "queued",
+        };
+        let kind = match self.kind {
+            Kind::Normal => "normal",
+            Kind::Exit => "exit",
+        };
+        write!(w, "{state}\t{}\t{}\t{}\t{kind}\t", self.qid, self.taskid.as_usize(), escape(&self.name))?;
+        match self.next {
+            Some(ca) => write!(w, "{}:{}", ca.taskid().as_usize(), ca.pi().0)?,
+            None => write!(w, "-")?,
+        }
+        for cond in &self.conds {
+            match cond {
+                Some(value) => write!(w, "\t={}", escape(value))?,
+                None => write!(w, "\t-")?,
+            }
+        }
+        writeln!(w)
+    }
+
+    fn parse(text:&str, line:usize)->Result<Self,CheckpointError> {
+        let malformed = |reason|CheckpointError::Parse { line, reason };
+        let mut fields = text.split('\t');
+        let mut field = |reason|fields.next().ok_or(malformed(reason));
+        let state = match field("no state")? {
+            "waiting" => State::Waiting,
+            "queued" => State::Queued,
+            _ => return Err(malformed("unknown state")),
+        };
+        let qid = field("no qid")?.parse().map_err(|_|malformed("invalid qid"))?;
+        let taskid = field("no taskid")?.parse().map_err(|_|malformed("invalid taskid"))?;
+        let name = unescape(field("no name")?).ok_or(malformed("invalid escape in the name"))?;
+        let kind = match field("no kind")? {
+            "normal" => Kind::Normal,
+            "exit" => Kind::Exit,
+            _ => return Err(malformed("unknown kind")),
+        };
+        let next = match field("no next")? {
+            "-" => None,
+            next => Some(parse_ca(next).ok_or(malformed("invalid next"))?),
+        };
+        let conds = fields
+            .map(|cond|match cond {
+                "-" => Ok(None),
+                _ => cond.strip_prefix('=')
+                    .and_then(unescape)
+                    .map(Some)
+                    .ok_or(malformed("invalid cond")),
+            })
+            .collect::<Result<_,_>>()?;
+        Ok(Self { state, qid, taskid: TaskId(NonZeroUsize::new(taskid)), name, kind, next, conds, line })
+    }
+}
+
+/// parses a cond address written as `<taskid>:<pi>`.
+pub(crate) fn parse_ca(text:&str)->Option<CondAddr> {
+    let (taskid,pi) = text.split_once(':')?;
+    Some(CondAddr::from((TaskId(NonZeroUsize::new(taskid.parse().ok()?)), Pi(pi.parse().ok()?))))
+}
+
+pub(crate) fn escape(text:&str)->String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn unescape(text:&str)->Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+/// Saves the waiting tasks and the queued ones, see `Pool::checkpoint()`.
+pub(crate) fn save<'a>(registry:&TaskRegistry, c1map:&C1map, queues:impl Iterator<Item = (usize,&'a Queue)>, mut w:impl Write)->Result<usize,CheckpointError> {
+    let mut records = Vec::new();
+    c1map.try_for_each(|qid,task|{
+        records.push(Record::of(registry, State::Waiting, qid, task)?);
+        Ok::<_,CheckpointError>(())
+    })?;
+    for (qid,queue) in queues {
+        queue.try_for_each(|task|{
+            records.push(Record::of(registry, State::Queued, qid, task)?);
+            Ok::<_,CheckpointError>(())
+        })?;
+    }
+    writeln!(w, "{HEADER}")?;
+    for record in &records {
+        record.write(&mut w)?;
+    }
+    w.flush()?;
+    debug!("{} tasks are saved to the checkpoint.", records.len());
+    Ok(records.len())
+}
+
+/// Restores the tasks saved by `save()`, see `Pool::restore()`.
+pub(crate) fn restore(registry:&TaskRegistry, submitter:impl Fn(usize)->Option<TaskSubmitter>, r:impl BufRead)->Result<usize,CheckpointError> {
+    let mut lines = r.lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    if header.trim_end() != HEADER {
+        return Err(CheckpointError::Version(header));
+    }
+    // all the tasks are decoded before any is submitted
+    let mut tasks = Vec::new();
+    for (i,line) in lines.enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = Record::parse(&line, i + 2)?;
+        let body = registry.get(&record.name)?;
+        if record.conds.len() != body.conds.len() {
+            return Err(CheckpointError::Mismatch { taskid: record.taskid, name: record.name });
+        }
+        let submitter = submitter(record.qid).ok_or(CheckpointError::NoQueue(record.qid))?;
+        tasks.push((submitter, (body.prepare)(&record)?));
+    }
+    let count = tasks.len();
+    for (submitter,submit) in tasks {
+        submit(&submitter).map_err(CheckpointError::Submit)?;
+    }
+    debug!("{count} tasks are restored from the checkpoint.");
+    Ok(count)
+}
+
+#[test]
+fn test_checkpoint_restore() {
+    use std::sync::mpsc;
+    use crate::{Pool, TaskBuildNew};
+
+    let (tx,rx) = mpsc::channel();
+    let report = move|sum:i32,label:String|tx.send(format!("{label}={sum}")).unwrap();
+    let sum = |a:i32,b:i32|a+b;
+    let seven = ||7;
+    let mut registry = TaskRegistry::new();
+    registry.register("report", report.clone()).register("sum", sum).register("seven", seven);
+
+    // task#3 reports the sum of task#2, whose cond#1 comes from the queued task#1
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let t3 = submitter.submit((report, TaskId::from(3)).into_task().named("report")
+        .default_cond(Pi::PI1, "a\tb".to_string())).unwrap();
+    let t2 = submitter.submit((sum, TaskId::from(2)).into_task().named("sum").to((t3,Pi::PI0).into())
+        .default_cond(Pi::PI0, 35)).unwrap();
+    submitter.submit((seven, TaskId::from(1)).into_task().named("seven").to((t2,Pi::PI1).into())).unwrap();
+
+    let mut snapshot = Vec::new();
+    assert_eq!(pool.checkpoint(&registry, &mut snapshot).unwrap(), 3);
+    assert_eq!(String::from_utf8(snapshot.clone()).unwrap(), "\
+        taskorch-checkpoint 1\n\
+        waiting\t1\t2\tsum\tnormal\t3:0\t=35\t-\n\
+        waiting\t1\t3\treport\tnormal\t-\t-\t=a\\tb\n\
+        queued\t1\t1\tseven\tnormal\t2:1\n");
+
+    // an unnamed task can not be saved
+    submitter.submit((||{}, TaskId::from(4)).into_task()).unwrap();
+    assert!(matches!(pool.checkpoint(&registry, io::sink()), Err(CheckpointError::Unnamed(_))));
+
+    let mut restored = Pool::new();
+    let qid = restored.insert_queue(&Queue::new()).unwrap();
+    assert!(matches!(restored.restore(&registry, "taskorch-checkpoint 2\n".as_bytes()), Err(CheckpointError::Version(_))));
+    let malformed = "taskorch-checkpoint 1\nwaiting\t1\t2\tsum\tnormal\t3:0\t=x\t-\n";
+    assert!(matches!(restored.restore(&registry, malformed.as_bytes()), Err(CheckpointError::Decode { line: 2, pi: Pi::PI0, .. })));
+    assert_eq!(restored.restore(&registry, snapshot.as_slice()).unwrap(), 3);
+    restored.spawn_thread_for(qid);
+    assert_eq!(rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap(), "a\tb=42");
+    restored.task_submitter(qid).unwrap().submit((||{}).into_exit_task()).unwrap();
+    restored.join();
+}
+
+#[test]
+fn test_checkpoint_delayed() {
+    use std::{sync::mpsc, time::Duration};
+    use crate::{Pool, TaskBuildNew};
+
+    let (tx,rx) = mpsc::channel();
+    let query = move||tx.send(()).unwrap();
+    let mut registry = TaskRegistry::new();
+    registry.register("query", query.clone());
+
+    // the token is held here, so the task is parked by the worker
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    pool.set_resource_limit("db", 1);
+    let (held,postdo,permit) = pool.shared.limits.acquire(
+        Box::new((||{}).into_task().requires("db", 1).0), Box::new(|_|()), &submitter.queue).unwrap();
+    drop((held,postdo));
+    submitter.submit((query, TaskId::from(1)).into_task().named("query").requires("db", 1)).unwrap();
+    pool.spawn_thread_for(qid);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(matches!(pool.checkpoint(&registry, io::sink()), Err(CheckpointError::Delayed(1))));
+    drop(permit);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(pool.checkpoint(&registry, io::sink()).unwrap(), 0);
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+}
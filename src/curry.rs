@@ -1,6 +1,12 @@
 // #![feature(unboxed_closures)]
 
-use std::{any::Any, marker::PhantomData};
+use std::{
+    any::Any,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
 use crate::meta::TupleOpt;
 
 // #[derive(Debug)]
@@ -112,6 +118,14 @@ pub(crate) trait CallOnce {
     fn call_once(self)->Self::R;
     fn count(&self)->usize;
     fn as_param_mut(&mut self)->Option<&mut dyn CallParam>;
+    /// runs once more without being consumed, only for the re-runnable callers, see `Rerun`.
+    fn call_again(&mut self)->Option<Self::R> {
+        None
+    }
+    /// polls the future of the body, only for the asynchronous callers, see `Awaited`.
+    fn poll(&mut self, _cx:&mut Context<'_>)->Option<Poll<Self::R>> {
+        None
+    }
 }
 
 #[allow(unused)]
@@ -128,6 +142,10 @@ pub(crate) trait CallParam {
     fn set(&mut self, i:usize, value: &dyn Any)->bool;
     fn typename(&self, i:usize)->&'static str;
     fn is_full(&self)->bool;
+    fn is_set(&self, i:usize)->bool;
+    fn arity(&self)->usize;
+    /// the value of the cond#`i` if filled
+    fn get(&self, i:usize)->Option<&dyn Any>;
 }
 
 /// Fn()->R
@@ -447,6 +465,18 @@ impl<F,P1:Clone+'static,R> CallParam for Currier<F,(P1,),R>
     fn is_full(&self)->bool {
         self.c.0.is_some()
     }
+    fn is_set(&self, i:usize)->bool {
+        i == 0 && self.c.0.is_some()
+    }
+    fn arity(&self)->usize {
+        1
+    }
+    fn get(&self, i:usize)->Option<&dyn Any> {
+        match i {
+            0 => self.c.0.as_ref().map(|p1|p1 as &dyn Any),
+            _ => None,
+        }
+    }
 }
 
 
@@ -646,6 +676,25 @@ macro_rules! impl_currier_call {
             fn is_full(&self)->bool {
                 $(self.c.$i.is_some()) &&+
             }
+            fn is_set(&self, i:usize)->bool {
+                match i {
+                    $(
+                    $i => self.c.$i.is_some(),
+                    )+
+                    _ => false
+                }
+            }
+            fn arity(&self)->usize {
+                [$($i),+].len()
+            }
+            fn get(&self, i:usize)->Option<&dyn Any> {
+                match i {
+                    $(
+                    $i => self.c.$i.as_ref().map(|$p|$p as &dyn Any),
+                    )+
+                    _ => None
+                }
+            }
         }
     };
 }
@@ -699,3 +748,99 @@ mod test_2 {
     }
 }
 
+
+
+/// A re-runnable caller, which can be called again and again with the same conds.
+///
+/// It is built upon `CallMut`, the conds are cloned for each call rather than consumed.
+pub struct Rerun<C>(pub(crate) C);
+
+impl<C:CallMut> CallOnce for Rerun<C> {
+    type R = C::R;
+    fn call_once(mut self)->Self::R {
+        self.0.call_mut()
+    }
+    fn count(&self)->usize {
+        self.0.count()
+    }
+    fn as_param_mut(&mut self)->Option<&mut dyn CallParam> {
+        self.0.as_param_mut()
+    }
+    fn call_again(&mut self)->Option<Self::R> {
+        Some(self.0.call_mut())
+    }
+}
+
+/// An asynchronous caller, whose body returns a future polled to the result.
+///
+/// The body is called by the first poll, and the future is kept for the next polls until it is ready.
+pub struct Awaited<C,Fut>(AwaitState<C,Fut>);
+
+enum AwaitState<C,Fut> {
+    Called(C),
+    Polling(Pin<Box<Fut>>),
+    Done,
+}
+
+impl<C,Fut> Awaited<C,Fut> {
+    pub(crate) fn new(c:C)->Self {
+        Self(AwaitState::Called(c))
+    }
+}
+
+impl<C:CallOnce<R = Fut>,Fut:Future> CallOnce for Awaited<C,Fut> {
+    type R = Fut::Output;
+    /// blocks the current thread until the future is ready, only if not run by a worker.
+    fn call_once(mut self)->Self::R {
+        crate::future::block_on(|cx|match self.poll(cx) {
+            Some(polled) => polled,
+            None => unreachable!("an awaited caller is always polled!"),
+        })
+    }
+    fn count(&self)->usize {
+        match &self.0 {
+            AwaitState::Called(c) => c.count(),
+            _ => 0,
+        }
+    }
+    fn as_param_mut(&mut self)->Option<&mut dyn CallParam> {
+        match &mut self.0 {
+            AwaitState::Called(c) => c.as_param_mut(),
+            _ => None,
+        }
+    }
+    fn poll(&mut self, cx:&mut Context<'_>)->Option<Poll<Self::R>> {
+        if let AwaitState::Called(_) = self.0 {
+            let AwaitState::Called(c) = std::mem::replace(&mut self.0, AwaitState::Done) else {
+                unreachable!("the state has checked above!");
+            };
+            self.0 = AwaitState::Polling(Box::pin(c.call_once()));
+        }
+        let AwaitState::Polling(future) = &mut self.0 else {
+            unreachable!("the future is polled again after ready!");
+        };
+        let polled = future.as_mut().poll(cx);
+        if polled.is_ready() {
+            self.0 = AwaitState::Done;
+        }
+        Some(polled)
+    }
+}
+
+#[test]
+fn test_awaited() {
+    let mut c: Awaited<_,_> = Awaited::new(Currier::from(|a:i32|async move { a + 1 }));
+    c.as_param_mut().unwrap().set(0, &3);
+    assert_eq!(c.count(), 1);
+    assert_eq!(c.call_once(), 4);
+}
+
+#[test]
+fn test_rerun() {
+    let mut n = 0;
+    let mut c = Rerun(Currier::from(|a:i32|{n+=1; a+n}));
+    c.as_param_mut().unwrap().set(0, &10);
+    assert_eq!(c.call_again(), Some(11));
+    assert_eq!(c.call_again(), Some(12));
+    assert_eq!(c.call_once(), 13);
+}
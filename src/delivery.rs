@@ -0,0 +1,125 @@
+//! ## delivery module
+//!
+//! Why a value is not delivered to the cond of a waiting task, see `DeliveryError`,
+//! and the dead letters passed to the handlers registered by `Pool::on_dead_letter()`.
+
+use crate::task::{CondAddr, TaskId};
+
+/// The value is not delivered to the cond of a waiting task.
+///
+/// Returned by `TaskSubmitter::deliver()`, in `TaskError::Undelivered` by a `TaskFuture`
+/// whose task passes its result on by `to()`, and in the `DeadLetter` of each failed delivery.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DeliveryError {
+    /// the target task id is zero, `TaskId::NONE`.
+    ZeroTaskId(CondAddr),
+    /// the target task is not waiting: not submitted yet, released already or given up.
+    NotFound(CondAddr),
+    /// the target task has no params to fill.
+    NoParams(CondAddr),
+    /// the cond has the type `expected`, not the `actual` one of the value.
+    TypeMismatch { ca:CondAddr, expected:&'static str, actual:&'static str },
+}
+
+impl DeliveryError {
+    /// the address of the cond the value was delivered to
+    pub fn ca(&self)->CondAddr {
+        match self {
+            Self::ZeroTaskId(ca) | Self::NotFound(ca) | Self::NoParams(ca) => *ca,
+            Self::TypeMismatch { ca, .. } => *ca,
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>)->std::fmt::Result {
+        let ca = self.ca();
+        match self {
+            Self::ZeroTaskId(_) => write!(f, "task#{:?} is ZERO, the cond#{:?} is not available", ca.taskid(), ca.pi()),
+            Self::NotFound(_) => write!(f, "task#{:?} is not waiting, the cond#{:?} could not be updated", ca.taskid(), ca.pi()),
+            Self::NoParams(_) => write!(f, "task#{:?} has no params, the cond#{:?} could not be updated", ca.taskid(), ca.pi()),
+            Self::TypeMismatch { expected, actual, .. } =>
+                write!(f, "task#{:?}.cond#{:?} has type <{expected}> not identical to <{actual}>", ca.taskid(), ca.pi()),
+        }
+    }
+}
+
+impl std::error::Error for DeliveryError {}
+
+/// A value not delivered, passed to the handlers registered by `Pool::on_dead_letter()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadLetter {
+    /// the task the value comes from, `TaskId::NONE` if delivered from outside the pool
+    pub from: TaskId,
+    pub error: DeliveryError,
+    /// the value in its `Debug` format, the value itself is dropped
+    pub value: String,
+}
+
+#[test]
+fn test_dead_letter() {
+    use std::{future::Future, pin::Pin, sync::{mpsc, Arc, Mutex}};
+    use crate::{future::block_on, Pi, Pool, Queue, TaskBuildNew, TaskError};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let letters = Arc::new(Mutex::new(Vec::new()));
+    let l = letters.clone();
+    pool.on_dead_letter(move|letter|l.lock().unwrap().push(letter.clone()));
+
+    // task#1 passes an i32 to the String cond of task#2, and task#3 to task#4 not submitted
+    let (tx,rx) = mpsc::channel();
+    submitter.submit((move|s:String|tx.send(s).unwrap(), TaskId::from(2)).into_task()).unwrap();
+    let mut mismatched = submitter.submit_async((||7, TaskId::from(1)).into_task().to((TaskId::from(2),Pi::PI0).into()));
+    let mut missing = submitter.submit_async((||"lost", TaskId::from(3)).into_task().to((TaskId::from(4),Pi::PI0).into()));
+    pool.spawn_thread_for(qid);
+
+    let ca2 = (TaskId::from(2),Pi::PI0).into();
+    let ca4 = (TaskId::from(4),Pi::PI0).into();
+    let mismatch = DeliveryError::TypeMismatch { ca: ca2, expected: "alloc::string::String", actual: "i32" };
+    let r = block_on(|cx|Pin::new(&mut mismatched).poll(cx));
+    assert!(matches!(r, Err(TaskError::Undelivered(ref e)) if *e == mismatch), "{r:?}");
+    let r = block_on(|cx|Pin::new(&mut missing).poll(cx));
+    assert!(matches!(r, Err(TaskError::Undelivered(DeliveryError::NotFound(ca))) if ca == ca4), "{r:?}");
+    // delivered from outside the pool, and the error is returned as well
+    assert_eq!(submitter.deliver(ca2, 8u8).unwrap_err().ca(), ca2);
+    assert!(submitter.deliver(ca2, String::from("ok")).is_ok());
+    assert_eq!(rx.recv().unwrap(), "ok");
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+
+    let letters = letters.lock().unwrap();
+    assert_eq!(letters.len(), 3, "{letters:?}");
+    assert_eq!(letters[0], DeadLetter { from: TaskId::from(1), error: mismatch, value: String::from("7") });
+    assert_eq!(letters[1], DeadLetter { from: TaskId::from(3), error: DeliveryError::NotFound(ca4), value: String::from("\"lost\"") });
+    assert_eq!((letters[2].from, letters[2].value.as_str()), (TaskId::NONE, "8"));
+}
+
+#[test]
+fn test_delivery_races_cond_timeout() {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, thread, time::Duration};
+    use crate::{Pi, Pool, PoolEvent, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let delivered = Arc::new(AtomicUsize::new(0));
+    let d = delivered.clone();
+    pool.subscribe(move|event|if let PoolEvent::CondDelivered { .. } = event {
+        d.fetch_add(1, Ordering::Relaxed);
+    });
+    // a value accepted is never reported as not delivered, though the task times out meanwhile
+    let mut accepted = 0;
+    for i in 0..200 {
+        let task = (|_:i32|{}, TaskId::from(1000 + i)).into_task().cond_timeout(Duration::from_micros(500));
+        let id = submitter.submit(task).unwrap();
+        thread::sleep(Duration::from_micros(400 + (i as u64 % 20) * 10));
+        match submitter.deliver((id,Pi::PI0).into(), 1) {
+            Ok(_) => accepted += 1,
+            Err(e) => assert_eq!(e, DeliveryError::NotFound((id,Pi::PI0).into())),
+        }
+    }
+    assert_eq!(delivered.load(Ordering::Relaxed), accepted);
+}
@@ -0,0 +1,119 @@
+//! ## event module
+//!
+//! The transitions in the life of the tasks and the workers of a pool,
+//! passed to the listeners registered by `Pool::subscribe()`.
+
+use std::{
+    thread::ThreadId,
+    time::Duration,
+};
+
+use crate::task::{Kind, Pi, TaskId};
+
+/// A transition of a task, or of a worker, see `Pool::subscribe()`.
+///
+/// The events are emitted on the threads making the transitions right after them,
+/// so the events of different tasks interleave, as may those of a task made on different threads.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum PoolEvent {
+    /// the task is submitted to the queue `qid`
+    Submitted { taskid:TaskId, qid:usize },
+    /// the conditional task waits in the pool for its conds
+    WaitingForConds { taskid:TaskId },
+    /// the cond#`pi` of the waiting task is delivered from the task `from`
+    CondDelivered { taskid:TaskId, pi:Pi, from:TaskId },
+    /// the conditional task has all its conds and is added into the queue `qid`
+    Released { taskid:TaskId, qid:usize },
+    /// a worker starts to run the task popped from the queue `qid`
+    Started { taskid:TaskId, qid:usize, kind:Kind },
+    /// the task has run and its result is passed on, `elapsed` covers both
+    Completed { taskid:TaskId, qid:usize, elapsed:Duration },
+    /// the task has panicked, after its last retry if any
+    Panicked { taskid:TaskId, qid:usize },
+    /// the task has timed out or missed its deadline, its result is not passed on
+    Cancelled { taskid:TaskId, qid:usize },
+    /// the exit task has run, the worker exits
+    ExitTaskRun { taskid:TaskId, qid:usize },
+    /// the worker has stopped, after an exit task, the quit flag or a panic
+    WorkerExited { thread:ThreadId, name:Option<String> },
+}
+
+#[test]
+fn test_subscribe() {
+    use std::sync::{mpsc, Arc, Mutex};
+    use crate::{Pool, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let e = events.clone();
+    pool.subscribe(move|event|e.lock().unwrap().push(event.clone()));
+
+    let (tx,rx) = mpsc::channel();
+    let t2 = submitter.submit((move|a:i32|tx.send(a).unwrap(), TaskId::from(2)).into_task()).unwrap();
+    let t1 = submitter.submit((||5, TaskId::from(1)).into_task().to((t2,Pi::PI0).into())).unwrap();
+    pool.spawn_thread_for(qid);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(5));
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+
+    let events = events.lock().unwrap();
+    let of = |taskid:TaskId|events.iter()
+        .filter(|e|match e {
+            PoolEvent::Submitted { taskid:t, .. } | PoolEvent::WaitingForConds { taskid:t }
+            | PoolEvent::CondDelivered { taskid:t, .. } | PoolEvent::Released { taskid:t, .. }
+            | PoolEvent::Started { taskid:t, .. } | PoolEvent::Completed { taskid:t, .. }
+            | PoolEvent::Panicked { taskid:t, .. } | PoolEvent::Cancelled { taskid:t, .. }
+            | PoolEvent::ExitTaskRun { taskid:t, .. } => *t == taskid,
+            PoolEvent::WorkerExited { .. } => false,
+        })
+        .map(|e|match e {
+            // the elapsed time is not known ahead
+            PoolEvent::Completed { taskid, qid, .. } => PoolEvent::Completed { taskid:*taskid, qid:*qid, elapsed:Duration::ZERO },
+            e => e.clone(),
+        })
+        .collect::<Vec<_>>();
+    let completed = |taskid|PoolEvent::Completed { taskid, qid, elapsed:Duration::ZERO };
+    assert_eq!(of(t2), [
+        PoolEvent::Submitted { taskid:t2, qid },
+        PoolEvent::WaitingForConds { taskid:t2 },
+        PoolEvent::CondDelivered { taskid:t2, pi:Pi::PI0, from:t1 },
+        PoolEvent::Released { taskid:t2, qid },
+        PoolEvent::Started { taskid:t2, qid, kind:Kind::Normal },
+        completed(t2),
+    ]);
+    assert_eq!(of(t1), [
+        PoolEvent::Submitted { taskid:t1, qid },
+        PoolEvent::Started { taskid:t1, qid, kind:Kind::Normal },
+        completed(t1),
+    ]);
+    assert_eq!(of(TaskId::NONE).last(), Some(&PoolEvent::ExitTaskRun { taskid:TaskId::NONE, qid }));
+    assert!(matches!(events.last(), Some(PoolEvent::WorkerExited { .. })), "{events:?}");
+}
+
+#[test]
+fn test_listener_reenters() {
+    use std::{sync::mpsc, time::Duration};
+    use crate::{Pool, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    // the listener delivers to the task just submitted, and submits another one
+    let s = submitter.clone();
+    pool.subscribe(move|event|if let PoolEvent::Submitted { taskid, .. } = event {
+        if *taskid == TaskId::from(1) {
+            s.deliver((*taskid,Pi::PI0).into(), 5).unwrap();
+            s.submit((|_:i32|{}, TaskId::from(2)).into_task()).unwrap();
+        }
+    });
+    let (tx,rx) = mpsc::channel();
+    submitter.submit((move|a:i32|tx.send(a).unwrap(), TaskId::from(1)).into_task()).unwrap();
+    assert_eq!(submitter.deliver((TaskId::from(2),Pi::PI0).into(), 0), Ok(crate::Delivered::Released));
+    pool.spawn_thread_for(qid);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(5));
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+}
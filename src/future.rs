@@ -0,0 +1,330 @@
+//! ## future module
+//!
+//! The bridge to async code: the result of a task awaited by `TaskSubmitter::submit_async()`,
+//! and the tasks built with `awaited()` polled by the workers, see `TaskBuild::awaited()`.
+
+use std::{
+    any::Any,
+    future::Future,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::{
+    event::PoolEvent,
+    queue::PostDo,
+    stuck::Delayed,
+    submitter::TaskError,
+    task::{Task, TaskId},
+    Queue,
+};
+
+/// The result of a task submitted by `TaskSubmitter::submit_async()`, ready once the task has run.
+///
+/// Resolved with `TaskError::Dropped` if the task ends without a result.
+pub struct TaskFuture<R>(Arc<Mutex<Slot<R>>>);
+
+struct Slot<R> {
+    result: Option<Result<R,TaskError>>,
+    /// whether the result has been set, it is taken by the poll
+    done: bool,
+    waker: Option<Waker>,
+}
+
+impl<R> TaskFuture<R> {
+    pub(crate) fn new()->Self {
+        Self(Arc::new(Mutex::new(Slot { result: None, done: false, waker: None })))
+    }
+
+    /// the future ready with the error at once, e.g. the task is not submitted.
+    pub(crate) fn failed(e:TaskError)->Self {
+        let future = Self::new();
+        future.0.lock().unwrap().result = Some(Err(e));
+        future
+    }
+
+    /// the resolver of the future, carried by the task `taskid`.
+    pub(crate) fn resolver(&self, taskid:TaskId)->Resolver<R> {
+        Resolver { slot: self.0.clone(), taskid }
+    }
+}
+
+impl<R> Future for TaskFuture<R> {
+    type Output = Result<R,TaskError>;
+    fn poll(self:Pin<&mut Self>, cx:&mut Context<'_>)->Poll<Self::Output> {
+        let mut slot = self.0.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Takes the result of a task for its `TaskFuture`, or the error passing it on, dropped if the task ends without a result.
+pub(crate) type Resolve = Box<dyn FnOnce(Result<Box<dyn Any>,TaskError>) + Send>;
+
+/// Resolves a `TaskFuture` with the result of the task, or with `TaskError::Dropped` if dropped before.
+pub(crate) struct Resolver<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+    taskid: TaskId,
+}
+
+impl<R> Resolver<R> {
+    pub(crate) fn resolve(self, r:R) {
+        self.set(Ok(r));
+    }
+
+    pub(crate) fn fail(self, e:TaskError) {
+        self.set(Err(e));
+    }
+
+    fn set(&self, result:Result<R,TaskError>) {
+        let mut slot = self.slot.lock().unwrap();
+        if slot.done {
+            return;
+        }
+        slot.done = true;
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            drop(slot);
+            waker.wake();
+        }
+    }
+}
+
+impl<R> Drop for Resolver<R> {
+    fn drop(&mut self) {
+        self.set(Err(TaskError::Dropped(self.taskid)));
+    }
+}
+
+/// the result of a run to pass on by the postdo, None if the task runs again later, e.g. pending
+pub(crate) type Polled = Option<(Option<Box<dyn Any>>,Box<PostDo>)>;
+
+/// Polls a task built with `awaited()`, returns its result to pass on,
+/// or None if it is pending and put aside until woken.
+pub(crate) fn poll_popped(mut task:Box<dyn Task+Send>, postdo:Box<PostDo>, queue:&Queue)->Polled {
+    let parker = Arc::new(Parker(Mutex::new(Parking::Polling)));
+    let waker = Waker::from(parker.clone());
+    if let Some(watch) = &task.attr_mut().watch {
+        watch.wake_on_timeout(&waker);
+        if watch.timed_out() {
+            debug!(task_id=task.id(); "task#{:?} is pending beyond its timeout, and is dropped.", task.id());
+            return Some((None,postdo));
+        }
+    }
+    let polled = catch_unwind(AssertUnwindSafe(||task.poll(&mut Context::from_waker(&waker))));
+    let taskid = task.id();
+    match polled {
+        Ok(Poll::Ready(r)) => Some((r,postdo)),
+        Ok(Poll::Pending) => {
+            let mut state = parker.0.lock().unwrap();
+            if let Parking::Woken = *state {
+                drop(state);
+                debug!(task_id=taskid; "task#{taskid:?} is woken while polled, and added into its queue again.");
+                queue.add_boxtask(task, postdo);
+            } else {
+                debug!(task_id=taskid; "task#{taskid:?} is pending, put aside until woken.");
+                *state = Parking::Parked(task, postdo, queue.clone(), queue.delayed());
+            }
+            None
+        }
+        Err(panic) => {
+            queue.metrics().panicked(task.attr_mut().tag);
+            queue.emit(|qid|PoolEvent::Panicked { taskid, qid });
+            resume_unwind(panic)
+        }
+    }
+}
+
+/// The waker of a pending task, which adds the task into its queue again.
+struct Parker(Mutex<Parking>);
+
+enum Parking {
+    Polling,
+    /// woken before the poll returned
+    Woken,
+    /// counted as delayed in the pool while put aside
+    Parked(Box<dyn Task+Send>, Box<PostDo>, Queue, Option<Delayed>),
+    Done,
+}
+
+impl Wake for Parker {
+    fn wake(self:Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self:&Arc<Self>) {
+        let mut state = self.0.lock().unwrap();
+        match std::mem::replace(&mut *state, Parking::Done) {
+            Parking::Polling | Parking::Woken => *state = Parking::Woken,
+            Parking::Parked(task, postdo, queue, _delayed) => {
+                drop(state);
+                queue.add_boxtask(task, postdo);
+            }
+            Parking::Done => {}
+        }
+    }
+}
+
+/// Polls on the current thread until ready, parked while pending.
+pub(crate) fn block_on<T>(mut poll:impl FnMut(&mut Context<'_>)->Poll<T>)->T {
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(r) = poll(&mut cx) {
+            return r;
+        }
+        thread::park();
+    }
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self:Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::{Pi, Pool, TaskBuildNew};
+    use super::*;
+
+    /// a future ready at the second poll, woken from another thread meanwhile
+    struct Later(bool);
+    impl Future for Later {
+        type Output = ();
+        fn poll(mut self:Pin<&mut Self>, cx:&mut Context<'_>)->Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            let waker = cx.waker().clone();
+            thread::spawn(move||{
+                thread::sleep(Duration::from_millis(20));
+                waker.wake();
+            });
+            Poll::Pending
+        }
+    }
+
+    fn wait<R>(mut future:TaskFuture<R>)->Result<R,TaskError> {
+        block_on(|cx|Pin::new(&mut future).poll(cx))
+    }
+
+    #[test]
+    fn test_submit_async() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        // task#1 awaits a future without holding the only worker, and passes its output to task#2
+        let t2 = submitter.submit_async((|a:i32|a + 1, TaskId::from(2)).into_task());
+        let l = log.clone();
+        let t1 = submitter.submit_async((move||async move {
+            Later(false).await;
+            l.lock().unwrap().push("async");
+            21
+        }, TaskId::from(1)).into_task().awaited().to((TaskId::from(2),Pi::PI0).into()));
+        let l = log.clone();
+        let unit = submitter.submit_async((move||l.lock().unwrap().push("sync")).into_task());
+        let dup = submitter.submit_async((|_:i32|{}, TaskId::from(2)).into_task());
+        assert!(matches!(wait(dup), Err(TaskError::TaskIdAlreadyExists(_))));
+        // given up before it runs
+        let given_up = submitter.submit_async((|a:i32|a, TaskId::from(3)).into_task().cond_timeout(Duration::from_millis(10)));
+        pool.spawn_thread_for(qid);
+
+        assert!(matches!(wait(given_up), Err(TaskError::Dropped(_))));
+        assert_eq!(wait(unit).unwrap(), ());
+        assert_eq!(wait(t1).unwrap(), 21);
+        assert_eq!(wait(t2).unwrap(), 22);
+        assert_eq!(*log.lock().unwrap(), ["sync", "async"]);
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+    }
+
+    #[test]
+    fn test_unit_result() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        // a unit result is not passed on by to(), by submit() or by submit_async()
+        let target = submitter.submit((|_:()|{}, TaskId::from(1)).into_task()).unwrap();
+        submitter.submit((||{}).into_task().to((target,Pi::PI0).into())).unwrap();
+        let unit = submitter.submit_async((||{}).into_task().to((target,Pi::PI0).into()));
+        pool.spawn_thread_for(qid);
+        assert_eq!(wait(unit).unwrap(), ());
+        assert!(pool.pending_tasks().iter().any(|task|task.taskid == target && !task.conds[0].filled));
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+    }
+
+    #[test]
+    fn test_awaited_timeout() {
+        use crate::PoolEvent;
+
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = std::sync::mpsc::channel();
+        pool.subscribe(move|event|if let PoolEvent::Cancelled { taskid, .. } = event {
+            tx.send(*taskid).unwrap();
+        });
+        // never resolves nor wakes, but is dropped at the timeout counted from its first poll
+        let never = submitter.submit_async((||std::future::pending::<i32>(), TaskId::from(1)).into_task()
+            .awaited()
+            .timeout(Duration::from_millis(20)));
+        // woken every 5ms, pending each time
+        let woken = submitter.submit_async((||std::future::poll_fn(|cx:&mut Context<'_>|{
+            let waker = cx.waker().clone();
+            thread::spawn(move||{
+                thread::sleep(Duration::from_millis(5));
+                waker.wake();
+            });
+            Poll::<i32>::Pending
+        }), TaskId::from(2)).into_task().awaited().timeout(Duration::from_millis(20)));
+        pool.spawn_thread_for(qid);
+        assert!(matches!(wait(never), Err(TaskError::Dropped(_))));
+        assert!(matches!(wait(woken), Err(TaskError::Dropped(_))));
+        let mut cancelled = vec![rx.recv().unwrap(), rx.recv().unwrap()];
+        cancelled.sort_by_key(|taskid|taskid.0);
+        assert_eq!(cancelled, [TaskId::from(1), TaskId::from(2)]);
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+    }
+
+    #[test]
+    fn test_awaited_exit() {
+        use crate::PoolEvent;
+
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let exits = Arc::new(Mutex::new(0));
+        let e = exits.clone();
+        pool.subscribe(move|event|if let PoolEvent::ExitTaskRun { .. } = event {
+            *e.lock().unwrap() += 1;
+        });
+        // the worker keeps serving while the exit task is pending, and exits once it finishes
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let l = log.clone();
+        submitter.submit((move||async move {
+            Later(false).await;
+            l.lock().unwrap().push("exit");
+        }).into_exit_task().awaited()).unwrap();
+        let l = log.clone();
+        submitter.submit((move||l.lock().unwrap().push("sync")).into_task()).unwrap();
+        pool.spawn_thread_for(qid);
+        pool.join();
+        assert_eq!(*log.lock().unwrap(), ["sync", "exit"]);
+        assert_eq!(*exits.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,204 @@
+//! ## hook module
+//!
+//! The callbacks registered on a pool, run by its workers when they start and stop,
+//! and around each task they run, and the listeners of the events of the pool.
+
+use std::{
+    sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use crate::delivery::DeadLetter;
+use crate::event::PoolEvent;
+use crate::task::{Kind, TaskId};
+
+/// The task run by a worker, passed to the hooks around the task.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TaskInfo {
+    pub taskid: TaskId,
+    pub kind: Kind,
+    /// the queue which the task is popped from
+    pub qid: usize,
+}
+
+type WorkerHook = dyn Fn() + Send + Sync;
+type BeforeHook = dyn Fn(&TaskInfo) + Send + Sync;
+type AfterHook = dyn Fn(&TaskInfo, Duration) + Send + Sync;
+type Listener = dyn Fn(&PoolEvent) + Send + Sync;
+type DeadLetterHook = dyn Fn(&DeadLetter) + Send + Sync;
+
+/// The callbacks are called on a copy of the list, out of the lock,
+/// so a callback may register another one without a deadlock.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    on_start: RwLock<Vec<Arc<WorkerHook>>>,
+    on_stop: RwLock<Vec<Arc<WorkerHook>>>,
+    before_task: RwLock<Vec<Arc<BeforeHook>>>,
+    after_task: RwLock<Vec<Arc<AfterHook>>>,
+    listeners: RwLock<Vec<Arc<Listener>>>,
+    dead_letters: RwLock<Vec<Arc<DeadLetterHook>>>,
+    /// whether any listener, to skip building the events without locking
+    listened: AtomicBool,
+}
+
+/// the callbacks registered so far, copied out of the lock
+fn listed<F:?Sized>(list:&RwLock<Vec<Arc<F>>>)->Vec<Arc<F>> {
+    list.read().unwrap().clone()
+}
+
+impl Hooks {
+    pub(crate) fn add_on_start(&self, f:impl Fn() + Send + Sync + 'static) {
+        self.on_start.write().unwrap().push(Arc::new(f));
+    }
+    pub(crate) fn add_on_stop(&self, f:impl Fn() + Send + Sync + 'static) {
+        self.on_stop.write().unwrap().push(Arc::new(f));
+    }
+    pub(crate) fn add_before_task(&self, f:impl Fn(&TaskInfo) + Send + Sync + 'static) {
+        self.before_task.write().unwrap().push(Arc::new(f));
+    }
+    pub(crate) fn add_after_task(&self, f:impl Fn(&TaskInfo, Duration) + Send + Sync + 'static) {
+        self.after_task.write().unwrap().push(Arc::new(f));
+    }
+
+    pub(crate) fn add_listener(&self, f:impl Fn(&PoolEvent) + Send + Sync + 'static) {
+        self.listeners.write().unwrap().push(Arc::new(f));
+        self.listened.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn add_dead_letter(&self, f:impl Fn(&DeadLetter) + Send + Sync + 'static) {
+        self.dead_letters.write().unwrap().push(Arc::new(f));
+    }
+
+    /// passes the dead letter built by `letter` to the handlers, if any
+    pub(crate) fn dead_letter(&self, letter:impl FnOnce()->DeadLetter) {
+        let handlers = listed(&self.dead_letters);
+        if handlers.is_empty() {
+            return;
+        }
+        let letter = letter();
+        for f in handlers.iter() {
+            f(&letter);
+        }
+    }
+
+    /// passes the event built by `event` to the listeners, if any
+    pub(crate) fn emit(&self, event:impl FnOnce()->PoolEvent) {
+        if !self.listened.load(Ordering::Acquire) {
+            return;
+        }
+        let event = event();
+        for f in listed(&self.listeners).iter() {
+            f(&event);
+        }
+    }
+
+    pub(crate) fn before(&self, info:&TaskInfo) {
+        for f in listed(&self.before_task).iter() {
+            f(info);
+        }
+    }
+    pub(crate) fn after(&self, info:&TaskInfo, elapsed:Duration) {
+        for f in listed(&self.after_task).iter() {
+            f(info, elapsed);
+        }
+    }
+}
+
+/// A worker between its start and stop, the stop hooks run when dropped,
+/// also when the worker unwinds from a panicking task.
+pub(crate) struct Running(Option<Arc<Hooks>>);
+
+impl Running {
+    pub(crate) fn start(hooks:Option<Arc<Hooks>>)->Self {
+        if let Some(hooks) = &hooks {
+            for f in listed(&hooks.on_start).iter() {
+                f();
+            }
+        }
+        Self(hooks)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(hooks) = &self.0 {
+            for f in listed(&hooks.on_stop).iter() {
+                f();
+            }
+            hooks.emit(||{
+                let thread = thread::current();
+                PoolEvent::WorkerExited { thread: thread.id(), name: thread.name().map(String::from) }
+            });
+        }
+    }
+}
+
+#[test]
+fn test_hooks() {
+    use std::sync::Mutex;
+    use crate::{Pool, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let e = events.clone();
+    pool.on_start(move||e.lock().unwrap().push(String::from("start")));
+    let e = events.clone();
+    pool.on_stop(move||e.lock().unwrap().push(String::from("stop")));
+    let e = events.clone();
+    pool.before_task(move|info|e.lock().unwrap().push(format!("before {:?} {:?}", info.taskid, info.kind)));
+    let e = events.clone();
+    pool.after_task(move|info,_elapsed|e.lock().unwrap().push(format!("after {:?}", info.taskid)));
+
+    submitter.submit((||{}, TaskId::from(1)).into_task()).unwrap();
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.spawn_thread_for(qid);
+    pool.join();
+    assert_eq!(*events.lock().unwrap(), [
+        "start",
+        "before TaskId(1) Normal", "after TaskId(1)",
+        "before TaskId(None) Exit", "after TaskId(None)",
+        "stop",
+    ]);
+}
+
+#[test]
+fn test_hooks_reenter() {
+    use std::sync::atomic::AtomicUsize;
+    use crate::{delivery::DeliveryError, task::Pi};
+
+    // each callback registers another one of its kind, on the same thread
+    let hooks = Arc::new(Hooks::default());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let (h,c) = (Arc::downgrade(&hooks),calls.clone());
+    hooks.add_listener(move|_|if let Some(hooks) = h.upgrade() {
+        c.fetch_add(1, Ordering::Relaxed);
+        hooks.add_listener(|_|());
+    });
+    let (h,c) = (Arc::downgrade(&hooks),calls.clone());
+    hooks.add_dead_letter(move|_|if let Some(hooks) = h.upgrade() {
+        c.fetch_add(1, Ordering::Relaxed);
+        hooks.add_dead_letter(|_|());
+    });
+    let (h,c) = (Arc::downgrade(&hooks),calls.clone());
+    hooks.add_before_task(move|_|if let Some(hooks) = h.upgrade() {
+        c.fetch_add(1, Ordering::Relaxed);
+        hooks.add_after_task(|_,_|());
+    });
+    let (h,c) = (Arc::downgrade(&hooks),calls.clone());
+    hooks.add_on_start(move||if let Some(hooks) = h.upgrade() {
+        c.fetch_add(1, Ordering::Relaxed);
+        hooks.add_on_stop(||());
+    });
+    let info = TaskInfo { taskid: TaskId::NONE, kind: Kind::Normal, qid: 1 };
+    drop(Running::start(Some(hooks.clone())));
+    hooks.before(&info);
+    hooks.after(&info, Duration::ZERO);
+    hooks.emit(||PoolEvent::Released { taskid: TaskId::NONE, qid: 1 });
+    let error = DeliveryError::NotFound((TaskId::NONE,Pi::PI0).into());
+    hooks.dead_letter(||DeadLetter { from: TaskId::NONE, error, value: String::new() });
+    // the listener gets the exit of the worker and the release
+    assert_eq!(calls.load(Ordering::Relaxed), 5);
+}
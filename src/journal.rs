@@ -0,0 +1,214 @@
+//! ## journal module
+//!
+//! Appends each cond delivered and each task completed to a journal, see `Pool::set_journal()`,
+//! and replays it after a crash to fill the conds again and to skip the tasks completed, see `Pool::replay()`.
+
+use std::{
+    collections::HashSet,
+    io::{BufRead, Write},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    checkpoint::{escape, parse_ca, unescape, CheckpointError, TaskRegistry},
+    curry::CallParam,
+    queue::C1map,
+    task::{CondAddr, TaskId},
+    Queue,
+};
+
+const HEADER: &str = "taskorch-journal 1";
+
+/// The journal of a pool, the deliveries are encoded by the bodies registered by the names of their targets.
+pub(crate) struct Journal {
+    registry: Arc<TaskRegistry>,
+    w: Mutex<Box<dyn Write + Send>>,
+    /// the names of the tasks whose conds are not journaled, warned once
+    unjournaled: Mutex<HashSet<Option<&'static str>>>,
+}
+
+impl Journal {
+    pub(crate) fn new(registry:Arc<TaskRegistry>, mut w:Box<dyn Write + Send>)->std::io::Result<Self> {
+        writeln!(w, "{HEADER}")?;
+        w.flush()?;
+        Ok(Self { registry, w: Mutex::new(w), unjournaled: Mutex::new(HashSet::new()) })
+    }
+
+    /// the record of the cond#`i` of the task named, just filled from the task `from`, to `append()`.
+    pub(crate) fn delivered(&self, from:TaskId, ca:CondAddr, name:Option<&'static str>, param:&dyn CallParam)->Option<String> {
+        let value = name.and_then(|name|self.registry.encode(name, param, ca.pi().0 as usize));
+        let Some(value) = value else {
+            // warned once per name, the deliveries to the unnamed tasks are many
+            if self.unjournaled.lock().unwrap().insert(name) {
+                warn!(task_id=ca.taskid(), pi=ca.pi(); "task#{:?} is not named after a registered body, its conds are not journaled, nor those of the tasks named {name:?}.", ca.taskid());
+            }
+            return None;
+        };
+        Some(format!("deliver\t{}\t{}\t{}:{}\t={}",
+            now_us(), from.as_usize(), ca.taskid().as_usize(), ca.pi().0, escape(&value)))
+    }
+
+    /// records the task completed, only a task with an id can be skipped on replay.
+    pub(crate) fn completed(&self, taskid:TaskId) {
+        if taskid.0.is_some() {
+            self.append(format!("complete\t{}\t{}", now_us(), taskid.as_usize()));
+        }
+    }
+
+    /// each record is written and flushed as a whole line, so a crash loses at most the last one.
+    pub(crate) fn append(&self, record:String) {
+        let mut w = self.w.lock().unwrap();
+        if let Err(_e) = w.write_all(format!("{record}\n").as_bytes()).and_then(|_|w.flush()) {
+            error!("failed to append to the journal: {_e}");
+        }
+    }
+}
+
+fn now_us()->u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d|d.as_micros())
+}
+
+/// A line of the journal.
+enum Entry {
+    Deliver { from:TaskId, ca:CondAddr, value:String },
+    Complete(TaskId),
+}
+
+fn parse(text:&str, line:usize)->Result<Entry,CheckpointError> {
+    let malformed = |reason|CheckpointError::Parse { line, reason };
+    let fields: Vec<&str> = text.split('\t').collect();
+    let taskid = |field:&str|field.parse().map(|id|TaskId(NonZeroUsize::new(id))).map_err(|_|malformed("invalid taskid"));
+    match fields.as_slice() {
+        ["deliver", _ts, from, ca, value] => Ok(Entry::Deliver {
+            from: taskid(from)?,
+            ca: parse_ca(ca).ok_or(malformed("invalid cond address"))?,
+            value: value.strip_prefix('=').and_then(unescape).ok_or(malformed("invalid value"))?,
+        }),
+        ["complete", _ts, id] => Ok(Entry::Complete(taskid(id)?)),
+        _ => Err(malformed("unknown record")),
+    }
+}
+
+/// Replays the journal, see `Pool::replay()`.
+pub(crate) fn replay<'a>(registry:&TaskRegistry, c1map:&C1map, queues:impl Iterator<Item = &'a Queue> + Clone, r:impl BufRead)->Result<usize,CheckpointError> {
+    let mut lines = r.lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    if header.trim_end() != HEADER {
+        return Err(CheckpointError::Version(header));
+    }
+    // the whole journal is parsed before anything is replayed
+    let mut entries = Vec::new();
+    for (i,line) in lines.enumerate() {
+        let line = line?;
+        // a header is appended each time the journal is opened again
+        if line.is_empty() || line.starts_with('#') || line == HEADER {
+            continue;
+        }
+        entries.push((i + 2, parse(&line, i + 2)?));
+    }
+    let completed: HashSet<usize> = entries.iter()
+        .filter_map(|(_,entry)|match entry {
+            Entry::Complete(taskid) => Some(taskid.as_usize()),
+            Entry::Deliver { .. } => None,
+        })
+        .collect();
+    for taskid in &completed {
+        let taskid = TaskId::new(*taskid);
+        let discarded = c1map.discard(taskid) as usize
+            + queues.clone().map(|queue|queue.discard(taskid)).sum::<usize>();
+        if discarded > 0 {
+            debug!(task_id=taskid; "task#{taskid:?} has completed before, and is skipped.");
+        }
+    }
+    let mut replayed = 0;
+    for (_line,entry) in &entries {
+        let Entry::Deliver { from:_from, ca, value } = entry else {
+            continue;
+        };
+        if completed.contains(&ca.taskid().as_usize()) {
+            continue;
+        }
+        let filled = c1map.replay_ci(ca, |name,param|registry.decode(name, param, ca.pi().0 as usize, value));
+        if filled.is_none() {
+            warn!(task_id=ca.taskid(), pi=ca.pi(), from_task=*_from;
+                "line {_line}: task#{:?}.cond#{:?} is not waiting or the value can not be decoded, the delivery is skipped.", ca.taskid(), ca.pi());
+            continue;
+        }
+        replayed += 1;
+    }
+    debug!("{replayed} deliveries are replayed, {} tasks completed are skipped.", completed.len());
+    Ok(replayed)
+}
+
+#[test]
+fn test_journal_replay() {
+    use std::{sync::mpsc, time::Duration};
+    use crate::{Pi, Pool, PoolEvent, TaskBuildNew};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for Buffer {
+        fn write(&mut self, buf:&[u8])->std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self)->std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let (tx,rx) = mpsc::channel();
+    let report = move|sum:i32|tx.send(sum).unwrap();
+    let sum = |a:i32,b:i32|a+b;
+    let one = ||1;
+    let two = ||2;
+    let mut registry = TaskRegistry::new();
+    registry.register("report", report.clone()).register("sum", sum).register("one", one).register("two", two);
+    let registry = Arc::new(registry);
+    // task#2 sums task#1 on Q#1 and task#4 on Q#2, and passes it to task#3
+    let submit_all = |pool:&Pool, q1:usize, q2:usize| {
+        let s1 = pool.task_submitter(q1).unwrap();
+        let s2 = pool.task_submitter(q2).unwrap();
+        s2.submit((report.clone(), TaskId::from(3)).into_task().named("report")).unwrap();
+        s2.submit((sum, TaskId::from(2)).into_task().named("sum").to((TaskId::from(3),Pi::PI0).into())).unwrap();
+        s1.submit((one, TaskId::from(1)).into_task().named("one").to((TaskId::from(2),Pi::PI0).into())).unwrap();
+        s2.submit((two, TaskId::from(4)).into_task().named("two").to((TaskId::from(2),Pi::PI1).into())).unwrap();
+    };
+
+    // the pool crashes after task#1, before Q#2 is served
+    let buffer = Buffer::default();
+    let mut pool = Pool::new();
+    let q1 = pool.insert_queue(&Queue::new()).unwrap();
+    let q2 = pool.insert_queue(&Queue::new()).unwrap();
+    pool.set_journal(registry.clone(), buffer.clone()).unwrap();
+    let (done,completed) = mpsc::channel();
+    pool.subscribe(move|event|if let PoolEvent::Completed { taskid, .. } = event {
+        done.send(*taskid).unwrap();
+    });
+    submit_all(&pool, q1, q2);
+    pool.spawn_thread_for(q1);
+    assert_eq!(completed.recv_timeout(Duration::from_secs(5)), Ok(TaskId::from(1)));
+    pool.task_submitter(q1).unwrap().submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+    let journal = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let records: Vec<Vec<&str>> = journal.lines().map(|line|line.split('\t').collect()).collect();
+    assert_eq!(records.len(), 3, "{journal}");
+    assert_eq!(records[0], [HEADER]);
+    assert_eq!((records[1][0], &records[1][2..]), ("deliver", &["1", "2:0", "=1"][..]));
+    assert_eq!((records[2][0], &records[2][2..]), ("complete", &["1"][..]));
+
+    // the tasks are submitted again, task#1 is skipped and task#2 gets its cond#0 back
+    let mut pool = Pool::new();
+    let q1 = pool.insert_queue(&Queue::new()).unwrap();
+    let q2 = pool.insert_queue(&Queue::new()).unwrap();
+    submit_all(&pool, q1, q2);
+    assert_eq!(pool.replay(&registry, journal.as_bytes()).unwrap(), 1);
+    assert!(pool.queue(q1).unwrap().snapshot().is_empty());
+    assert!(pool.pending_tasks().iter().any(|task|task.taskid == TaskId::from(2) && task.conds[0].filled));
+    assert!(matches!(pool.replay(&registry, "taskorch-journal 9\n".as_bytes()), Err(CheckpointError::Version(_))));
+    pool.spawn_thread_for(q2);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(3));
+    pool.task_submitter(q2).unwrap().submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+}
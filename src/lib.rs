@@ -3,7 +3,8 @@
 use std::{
     collections::HashMap,
     sync::{atomic::{AtomicBool, Ordering}, Arc},
-    thread::{self, JoinHandle}
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 #[macro_use]
@@ -14,17 +15,56 @@ mod curry;
 mod queue;
 pub mod task;
 mod submitter;
+mod delivery;
+mod timer;
+mod cancel;
+mod resource;
+mod rate;
+mod worker;
+mod hook;
+mod event;
+mod stuck;
+mod pending;
+mod checkpoint;
+mod journal;
+mod sim;
+mod future;
+mod trace;
+mod metrics;
+#[cfg(feature = "metrics")]
+mod prometheus;
 use queue::C1map;
-pub use queue::{spawn_thread, Queue};
+use resource::Limits;
+use rate::TagRates;
+use hook::Hooks;
+use metrics::Registry;
+use stuck::Activity;
+pub use queue::{spawn_thread, spawn_thread_with, spawn_weighted_thread, Queue};
 pub use task::{
     CondAddr,TaskId,Pi,
+    CondTimeout,RetryPolicy,
+    DeadlineMiss,DeadlineMissed,
     Kind,
     TaskBuild,
     TaskBuildNew,TaskBuildOp,
     taskid_next,
 };
 
-pub use submitter::{TaskSubmitter,TaskError};
+pub use submitter::{TaskSubmitter,TaskError,Delivered};
+pub use delivery::{DeadLetter, DeliveryError};
+pub use future::TaskFuture;
+pub use cancel::CancelToken;
+pub use worker::WorkerConfig;
+pub use hook::TaskInfo;
+pub use event::PoolEvent;
+pub use stuck::{MissingCond, StuckTask};
+pub use pending::{CondState, PendingTask};
+pub use checkpoint::{CheckpointError, CondCodec, TaskRegistry};
+pub use sim::SimPool;
+pub use trace::{Trace, TraceRecorder};
+pub use crate::log::{JsonSink, LogLevel, LogRecord, LogSink, LogValue, RingBufferSink, StderrSink, StdoutSink, WriteSink};
+#[cfg(feature = "metrics")]
+pub use metrics::{HistogramSnapshot, PoolMetrics, QueueMetricsSnapshot, TagMetricsSnapshot, WorkerMetricsSnapshot};
 
 
 /// a handle to a thread spawned for queue
@@ -49,11 +89,23 @@ impl Jhandle {
     }
 }
 
+/// The state of a pool shared with the queues inserted into it.
+#[derive(Clone)]
+pub(crate) struct Shared {
+    pub(crate) limits: Limits,
+    pub(crate) rates: TagRates,
+    pub(crate) c1map: C1map,
+    pub(crate) hooks: Arc<Hooks>,
+    pub(crate) metrics: Registry,
+    pub(crate) activity: Arc<Activity>,
+}
+
 /// Pool, a container that holds and managers all resources, such as threads and queues
 pub struct Pool {
     queues: HashMap<usize,Queue>,
     jhands: HashMap<usize,Jhandle>,
     c1map: C1map,
+    shared: Shared,
     id_next: usize,
 }
 
@@ -61,10 +113,19 @@ impl Pool {
     pub fn new()-> Self {
         log::init_starttime();
         warn!("Pool created.");
+        let c1map = C1map::new();
         Self {
             queues: HashMap::new(),
             jhands: HashMap::new(),
-            c1map: C1map::new(),
+            c1map: c1map.clone(),
+            shared: Shared {
+                limits: Limits::default(),
+                rates: TagRates::default(),
+                c1map: c1map.clone(),
+                hooks: Arc::default(),
+                metrics: Registry::new(move||c1map.len()),
+                activity: Arc::default(),
+            },
             id_next: 0,
         }
     }
@@ -94,12 +155,283 @@ impl Pool {
     /// returns the queue ID recorded in pool
     pub fn insert_queue(&mut self,queue:&Queue)->Option<usize> {
         let id = self.next_id();
+        queue.bind(id, &self.shared);
         // update the queue
         let _r = self.queues.insert(id, queue.clone());
         debug!("Q#{id} created.");
         Some(id)
     }
 
+    /// Sets the count of tokens of the named resource, which the running tasks hold at most at a time.
+    ///
+    /// It is shared by all the queues of the pool, see `TaskBuild::requires()`.
+    pub fn set_resource_limit(&self, name:&'static str, limit:usize) {
+        self.shared.limits.set_limit(name, limit);
+        debug!("resource '{name}' is limited to {limit}.");
+    }
+
+    /// Sets the level of the log at runtime, within the level compiled in by the features `log-error`..`log-trace`.
+    ///
+    /// The log is process-wide, so is the level for all the pools.
+    /// The env var `TASKORCH_LOG` sets the levels at the start, a level alone is the default,
+    /// and `module=level` is the level of a module, e.g. `TASKORCH_LOG=debug,queue=trace`.
+    /// This sets the default and keeps the levels of the modules.
+    pub fn set_log_level(&self, level:LogLevel) {
+        log::set_level(level);
+    }
+
+    /// Sends the log to the sink rather than stdout, process-wide as `set_log_level()`.
+    ///
+    /// It takes the printed log only, not the one routed by the features `log-crate` and `tracing`.
+    pub fn set_log_sink(&self, sink:impl LogSink + 'static) {
+        log::set_sink(Arc::new(sink));
+    }
+
+    /// Lets the tasks with the `tag` start at most `n` per interval, with a burst of `n` at most.
+    ///
+    /// It is shared by all the queues of the pool, see `TaskBuild::tag()`.
+    /// A task over the rate reserves the next free token and goes back to the front of its queue
+    /// once that token comes, without holding a worker, so the tasks over the rate start in order.
+    pub fn set_tag_rate_limit(&self, tag:&'static str, n:u32, per:Duration) {
+        self.shared.rates.set_limit(tag, n, per);
+        debug!("tag '{tag}' is limited to {n} per {per:?}.");
+    }
+
+    /// Passes the transitions of the tasks and the workers of the pool to `listener`, see `PoolEvent`.
+    ///
+    /// The listener runs on the thread making the transition, e.g. the submitting thread or a worker,
+    /// so it is kept short. It is registered before the tasks are submitted, or else it misses their events.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Pool, PoolEvent};
+    /// let pool = Pool::new();
+    /// pool.subscribe(|event|if let PoolEvent::Panicked { taskid, .. } = event {
+    ///     eprintln!("task#{taskid:?} panicked");
+    /// });
+    /// ```
+    pub fn subscribe(&self, listener:impl Fn(&PoolEvent) + Send + Sync + 'static) {
+        self.shared.hooks.add_listener(listener);
+    }
+
+    /// The conditional tasks waiting for their conds, with the state of each cond, in the order of their ids.
+    ///
+    /// The tasks ready in a queue are listed by `Queue::snapshot()`.
+    pub fn pending_tasks(&self)->Vec<PendingTask> {
+        self.c1map.pending()
+    }
+
+    /// Saves the conditional tasks waiting for their conds, with the values filled,
+    /// and the tasks ready in the queues to `w`, in the format documented in `TaskRegistry`.
+    /// Returns the count of the tasks saved.
+    ///
+    /// Each task must be named after a body registered in `registry`, see `TaskBuild::named()`,
+    /// and must not distribute its result by `fan_tuple_with()`.
+    /// The other settings of a task, such as its retry or its deadline, are not saved.
+    ///
+    /// The tasks running are not saved, nor are their results once delivered after the checkpoint,
+    /// so it is taken when the pool is idle, e.g. before the workers are spawned or from `watch_stuck()`.
+    /// A task put aside, e.g. delayed by a retry or a rate limit, parked for a resource or pending as awaited,
+    /// can not be saved, and fails the checkpoint with `CheckpointError::Delayed` before anything is written.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Pi, Pool, Queue, TaskBuildNew as _, TaskId, TaskRegistry};
+    /// let sum = |a:i32,b:i32|a+b;
+    /// let mut registry = TaskRegistry::new();
+    /// registry.register("sum", sum);
+    ///
+    /// let mut pool = Pool::new();
+    /// let qid = pool.insert_queue(&Queue::new()).unwrap();
+    /// let submitter = pool.task_submitter(qid).unwrap();
+    /// submitter.submit((sum, TaskId::from(1)).into_task().named("sum").default_cond(Pi::PI0, 2)).unwrap();
+    ///
+    /// let mut snapshot = Vec::new();
+    /// pool.checkpoint(&registry, &mut snapshot).unwrap();
+    ///
+    /// // e.g. after a crash, the queues are inserted in the same order
+    /// let mut pool = Pool::new();
+    /// pool.insert_queue(&Queue::new()).unwrap();
+    /// assert_eq!(pool.restore(&registry, snapshot.as_slice()).unwrap(), 1);
+    /// ```
+    pub fn checkpoint(&self, registry:&TaskRegistry, w:impl std::io::Write)->Result<usize,CheckpointError> {
+        let delayed = self.shared.activity.delayed();
+        if delayed > 0 {
+            return Err(CheckpointError::Delayed(delayed));
+        }
+        let mut queues: Vec<_> = self.queues.iter().map(|(qid,queue)|(*qid,queue)).collect();
+        queues.sort_by_key(|(qid,_)|*qid);
+        checkpoint::save(registry, &self.c1map, queues.into_iter(), w)
+    }
+
+    /// Submits the tasks saved by `checkpoint()` to the queues with the same ids, see `checkpoint()`.
+    /// Returns the count of the tasks restored.
+    ///
+    /// All the tasks are decoded before any is submitted, so a malformed snapshot restores nothing.
+    /// The tasks are restored before the workers are spawned, or else a task restored may deliver
+    /// its result before the task waiting for it is restored.
+    pub fn restore(&self, registry:&TaskRegistry, r:impl std::io::BufRead)->Result<usize,CheckpointError> {
+        checkpoint::restore(registry, |qid|self.task_submitter(qid), r)
+    }
+
+    /// Appends each cond delivered to a waiting task and each task completed to the journal `w`,
+    /// e.g. a file opened to append, so that `replay()` recovers the progress after the last checkpoint.
+    ///
+    /// A record is a line with the time in microseconds since the Unix epoch:
+    /// * `deliver <ts> <from> <taskid>:<pi> =<value>` the cond#`pi` of the task filled from the task `from`,
+    ///   the value encoded by the body its target is named after, see `TaskBuild::named()`.
+    /// * `complete <ts> <taskid>` the task has completed, only recorded for a task with an id.
+    ///
+    /// The fields are separated by a tab and escaped as in the snapshot, see `TaskRegistry`.
+    /// A header `taskorch-journal 1` is written first, each time the journal is set.
+    /// A delivery to a task not named after a registered body is not recorded, with a warning.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use taskorch::{Pool, TaskRegistry};
+    /// let mut registry = TaskRegistry::new();
+    /// registry.register("sum", |a:i32,b:i32|a+b);
+    /// let pool = Pool::new();
+    /// let path = std::env::temp_dir().join("taskorch.journal");
+    /// let file = std::fs::OpenOptions::new().create(true).append(true).open(path).unwrap();
+    /// pool.set_journal(Arc::new(registry), file).unwrap();
+    /// ```
+    pub fn set_journal(&self, registry:Arc<TaskRegistry>, w:impl std::io::Write + Send + 'static)->std::io::Result<()> {
+        self.c1map.set_journal(journal::Journal::new(registry, Box::new(w))?);
+        Ok(())
+    }
+
+    /// Replays the journal written by `set_journal()`: the tasks completed are removed from the queues
+    /// and from the waiting ones, and the other deliveries fill the conds of the waiting tasks again, in order.
+    /// Returns the count of the deliveries replayed.
+    ///
+    /// The tasks are submitted again, or restored by `restore()`, before the replay and before
+    /// the workers are spawned. A delivery to a task not waiting is skipped with a warning.
+    /// The deliveries replayed are not journaled again.
+    pub fn replay(&self, registry:&TaskRegistry, r:impl std::io::BufRead)->Result<usize,CheckpointError> {
+        journal::replay(registry, &self.c1map, self.queues.values(), r)
+    }
+
+    /// The conditional tasks waiting for conds which nothing is going to deliver, if the pool is idle at the moment:
+    /// no worker runs a task, the queues are empty and no task is delayed for a retry or a rate limit.
+    /// Empty if the pool is not idle.
+    ///
+    /// A task with a pending optional cond or a `cond_timeout()` is not stuck, a timer resolves it.
+    /// The pool may be idle for a moment between two tasks, see `watch_stuck()` for a pool idle for a while.
+    pub fn stuck_tasks(&self)->Vec<StuckTask> {
+        stuck::stuck_now(&self.shared.activity, &self.c1map)
+    }
+
+    /// Inspects the pool every interval, and passes the stuck tasks to `f` once the pool has been idle
+    /// for a whole interval, see `stuck_tasks()`, it is the case `join()` waits forever.
+    /// They are passed once until the pool moves on, and the inspection ends with the pool.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use taskorch::Pool;
+    /// let pool = Pool::new();
+    /// pool.watch_stuck(Duration::from_secs(1), |stuck|for task in stuck {
+    ///     eprintln!("{task}");
+    /// });
+    /// ```
+    pub fn watch_stuck(&self, every:Duration, f:impl Fn(&[StuckTask]) + Send + Sync + 'static) {
+        stuck::watch(&self.shared.activity, self.c1map.clone(), every, Arc::new(f));
+    }
+
+    /// Runs `f` on each worker of the pool when it starts, before any task,
+    /// e.g. to open a thread-local connection.
+    ///
+    /// The hooks are registered before the workers are spawned, or else they miss the start.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::Pool;
+    /// let pool = Pool::new();
+    /// pool.on_start(||println!("{:?} starts", std::thread::current().id()));
+    /// ```
+    pub fn on_start(&self, f:impl Fn() + Send + Sync + 'static) {
+        self.shared.hooks.add_on_start(f);
+    }
+
+    /// Runs `f` on each worker of the pool when it stops, also when a task panics on it.
+    pub fn on_stop(&self, f:impl Fn() + Send + Sync + 'static) {
+        self.shared.hooks.add_on_stop(f);
+    }
+
+    /// Runs `f` on the worker right before it runs a task.
+    pub fn before_task(&self, f:impl Fn(&TaskInfo) + Send + Sync + 'static) {
+        self.shared.hooks.add_before_task(f);
+    }
+
+    /// Runs `f` on the worker right after a task completes and its result is passed on,
+    /// with the time taken by both.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::Pool;
+    /// let pool = Pool::new();
+    /// pool.after_task(|info,elapsed|println!("task#{:?} on Q#{} took {elapsed:?}", info.taskid, info.qid));
+    /// ```
+    pub fn after_task(&self, f:impl Fn(&TaskInfo, Duration) + Send + Sync + 'static) {
+        self.shared.hooks.add_after_task(f);
+    }
+
+    /// Passes each value not delivered to the cond of a waiting task to `f`, the dead-letter sink of the pool,
+    /// e.g. the result of a task `to()` a task given up, or of a type other than the cond.
+    ///
+    /// A value delivered by `TaskSubmitter::deliver()` is passed as well, besides its error returned.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::Pool;
+    /// let pool = Pool::new();
+    /// pool.on_dead_letter(|letter|eprintln!("{} from task#{:?} is dropped: {}", letter.value, letter.from, letter.error));
+    /// ```
+    pub fn on_dead_letter(&self, f:impl Fn(&DeadLetter) + Send + Sync + 'static) {
+        self.shared.hooks.add_dead_letter(f);
+    }
+
+    /// A snapshot of the counters of the queues and the workers.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::Pool;
+    /// let pool = Pool::new();
+    /// for q in pool.metrics().queues {
+    ///     println!("Q#{} completed {}, p99 run time {:?}", q.qid, q.completed, q.run.percentile(0.99));
+    /// }
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self)->PoolMetrics {
+        self.shared.metrics.snapshot()
+    }
+
+    /// The metrics in the Prometheus text exposition format, see `PoolMetrics::to_prometheus()`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_prometheus(&self)->String {
+        self.metrics().to_prometheus()
+    }
+
+    /// Serves the metrics in the Prometheus text format on `GET /metrics`,
+    /// returns the address listened on, e.g. with the port 0 chosen by the OS.
+    ///
+    /// The listener runs on its own thread as long as the process, and reads the metrics
+    /// of the queues inserted before or after it starts.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::Pool;
+    /// let pool = Pool::new();
+    /// let addr = pool.serve_metrics("127.0.0.1:0").unwrap();
+    /// println!("scrape http://{addr}/metrics");
+    /// ```
+    #[cfg(feature = "prometheus-http")]
+    pub fn serve_metrics(&self, addr:impl std::net::ToSocketAddrs)->std::io::Result<std::net::SocketAddr> {
+        prometheus::serve(self.shared.metrics.clone(), addr)
+    }
+
     /// return thread.id in pool
     pub fn spawn_thread_for(&mut self, qid:usize)->Option<usize> {
         let Some(queue) = self.queue(qid) else {
@@ -109,10 +441,52 @@ impl Pool {
         spawn_thread(queue).collect_into(self)
     }
 
+    /// Spawns a thread for the queue with the thread options of `cfg`, returns thread.id in pool.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Pool, Queue, WorkerConfig};
+    /// let mut pool = Pool::new();
+    /// let qid = pool.insert_queue(&Queue::new()).unwrap();
+    /// let cfg = WorkerConfig::new().name("media-{qid}-{n}").affinity(&[0]);
+    /// pool.spawn_thread_for_with(qid, &cfg);
+    /// # pool.exit_next_all();
+    /// ```
+    pub fn spawn_thread_for_with(&mut self, qid:usize, cfg:&WorkerConfig)->Option<usize> {
+        let Some(queue) = self.queue(qid) else {
+            error!("Q#{qid} does not exist; thread starting is not allowed.");
+            return None;
+        };
+        match spawn_thread_with(queue, cfg) {
+            Ok(jhandle) => jhandle.collect_into(self),
+            Err(_err) => {
+                error!("failed to spawn a thread for Q#{qid}: {_err}");
+                None
+            }
+        }
+    }
+
+    /// Spawns a thread serving several queues by weight, e.g. `[(qid1,3),(qid2,1)]`,
+    /// returns thread.id in pool.
+    ///
+    /// The threads spawned with the same weights form a worker group sharing the queues,
+    /// no queue starves while its weight is not zero, see `spawn_weighted_thread()`.
+    pub fn spawn_weighted_thread_for(&mut self, weights:&[(usize,u32)])->Option<usize> {
+        let mut queues = Vec::with_capacity(weights.len());
+        for (qid,weight) in weights {
+            let Some(queue) = self.queue(*qid) else {
+                error!("Q#{qid} does not exist; thread starting is not allowed.");
+                return None;
+            };
+            queues.push((queue,*weight));
+        }
+        spawn_weighted_thread(&queues).collect_into(self)
+    }
+
     fn insert_thread_handle(&mut self, jhandle:Jhandle)->Option<usize> {
         let id = self.next_id();
-        self.jhands.insert(id, jhandle)
-            .map(|_|id)
+        self.jhands.insert(id, jhandle);
+        Some(id)
     }
 
     #[allow(dead_code)]
@@ -1,14 +1,16 @@
 #![allow(dead_code)]
 
-#[allow(unused_imports)]
-use std::fmt::Display;
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::io::Write;
 use std::str::{from_utf8, from_utf8_unchecked};
-use std::sync::{Mutex, OnceLock};
-use std::time::Instant;
+use std::sync::{Arc, Mutex, Once, OnceLock, RwLock};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
 
-#[allow(dead_code)]
-#[derive(PartialEq, PartialOrd)]
-pub(crate) enum Level {
+/// The level of a log, from `Off` to the most verbose `Trace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
     Off,
     Error,
     Warn,
@@ -17,28 +19,47 @@ pub(crate) enum Level {
     Trace,
 }
 
-/// Log level options (mutually exclusive - select one or none)
-/// When multiple are selected, compilation will fail.
-pub(crate) const LEVEL: Level = {
-    #[cfg(feature = "log-error")]
-    const MAX_LEVEL: Level = Level::Error;
-    #[cfg(feature = "log-warn")]
-    const MAX_LEVEL: Level = Level::Warn;
-    #[cfg(feature = "log-info")]
-    const MAX_LEVEL: Level = Level::Info;
-    #[cfg(feature = "log-debug")]
-    const MAX_LEVEL: Level = Level::Debug;
-    #[cfg(feature = "log-trace")]
-    const MAX_LEVEL: Level = Level::Trace;
-    #[cfg(not(any(
-        feature = "log-error",
-        feature = "log-warn",
-        feature = "log-info",
-        feature = "log-debug",
-        feature = "log-trace",
-    )))]
-    const MAX_LEVEL: Level = Level::Off;
-    MAX_LEVEL
+impl LogLevel {
+    const ALL: [LogLevel; 6] = [Self::Off, Self::Error, Self::Warn, Self::Info, Self::Debug, Self::Trace];
+
+    fn name(self)->&'static str {
+        match self {
+            Self::Off => "off",
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+
+    /// parses the name of a level, case insensitive
+    fn parse(name:&str)->Option<Self> {
+        Self::ALL.into_iter().find(|level|level.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>)->std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// The most verbose level compiled in, the runtime filter works within it.
+/// If several level features are selected, the most verbose is taken.
+/// The `log` facade and `tracing` filter by themselves, all the levels are compiled in for them.
+pub(crate) const LEVEL: LogLevel = if cfg!(any(feature = "log-trace", feature = "log-crate", feature = "tracing")) {
+    LogLevel::Trace
+} else if cfg!(feature = "log-debug") {
+    LogLevel::Debug
+} else if cfg!(feature = "log-info") {
+    LogLevel::Info
+} else if cfg!(feature = "log-warn") {
+    LogLevel::Warn
+} else if cfg!(feature = "log-error") {
+    LogLevel::Error
+} else {
+    LogLevel::Off
 };
 
 pub(crate) const COLOR_RED: &'static str= "\x1b[31m";
@@ -78,14 +99,164 @@ static START_TIME: OnceLock<Instant> = OnceLock::new();
 pub(crate) static LOG_GLOBAL_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
 pub(crate) const NAME:&'static str = "taskorch";
 
+/// A log may lead with structured fields, `task_id`, `from_task`, `qid`, `pi` or `type_name`,
+/// separated from the message by `;`, e.g. `debug!(task_id=taskid, qid=qid; "task#{taskid:?} ...")`.
+/// The fields are recorded by the `tracing` and `log-crate` backends, and passed to a `LogSink`,
+/// the printed log has them in the message already.
 #[allow(unused_macros)]
+#[cfg(not(any(feature = "log-crate", feature = "tracing")))]
 macro_rules! log {
-    ($level:literal $color_head:expr, $color_tail:expr; $($args:tt)*) => {{
-        let msg = log_str!($level $color_head, $color_tail; $($args)*);
-        let lock = crate::log::LOG_GLOBAL_LOCK.get_or_init(||::std::sync::Mutex::new(()));
-        let _guard = lock.lock();
-        log_print!("{}",msg)
-    }}
+    (@ $level:tt $color_head:expr, $color_tail:expr; $fields:expr; $($args:tt)*) => {{
+        let level = level_of!($level);
+        if crate::log::enabled(level, module_path!()) {
+            match crate::log::sink() {
+                Some(sink) => crate::log::write(&*sink, level, module_path!(), file!(), line!(), &$fields, format_args!($($args)*)),
+                None => {
+                    let _: &[(&str,crate::log::LogValue<'_>)] = &$fields;
+                    let msg = log_str!($level $color_head, $color_tail; $($args)*);
+                    let lock = crate::log::LOG_GLOBAL_LOCK.get_or_init(||::std::sync::Mutex::new(()));
+                    let _guard = lock.lock();
+                    log_print!("{}",msg)
+                }
+            }
+        }
+    }};
+    ($level:tt $color_head:expr, $color_tail:expr; $($key:ident = $value:expr),+; $($args:tt)*) => {
+        log!(@ $level $color_head, $color_tail; [$((stringify!($key), crate::log::Field::value(&$value))),+]; $($args)*)
+    };
+    ($level:tt $color_head:expr, $color_tail:expr; $($args:tt)*) => {
+        log!(@ $level $color_head, $color_tail; []; $($args)*)
+    };
+}
+
+#[allow(unused_macros)]
+#[cfg(all(feature = "log-crate", not(feature = "tracing")))]
+macro_rules! log {
+    ($level:tt $color_head:expr, $color_tail:expr; $($key:ident = $value:expr),+; $($args:tt)*) => {
+        if crate::log::enabled(level_of!($level), module_path!()) {
+            ::log::log!(target: crate::log::NAME, log_level!($level),
+                $($key = crate::log::Field::field(&$value)),+; $($args)*)
+        }
+    };
+    ($level:tt $color_head:expr, $color_tail:expr; $($args:tt)*) => {
+        if crate::log::enabled(level_of!($level), module_path!()) {
+            ::log::log!(target: crate::log::NAME, log_level!($level), $($args)*)
+        }
+    };
+}
+
+#[allow(unused_macros)]
+#[cfg(feature = "tracing")]
+macro_rules! log {
+    ($level:tt $color_head:expr, $color_tail:expr; $($key:ident = $value:expr),+; $($args:tt)*) => {
+        if crate::log::enabled(level_of!($level), module_path!()) {
+            ::tracing::event!(target: crate::log::NAME, log_level!($level),
+                $($key = crate::log::Field::field(&$value),)+ $($args)*)
+        }
+    };
+    ($level:tt $color_head:expr, $color_tail:expr; $($args:tt)*) => {
+        if crate::log::enabled(level_of!($level), module_path!()) {
+            ::tracing::event!(target: crate::log::NAME, log_level!($level), $($args)*)
+        }
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! level_of {
+    ("error") => { crate::log::LogLevel::Error };
+    ("warn") => { crate::log::LogLevel::Warn };
+    ("info") => { crate::log::LogLevel::Info };
+    ("debug") => { crate::log::LogLevel::Debug };
+    ("trace") => { crate::log::LogLevel::Trace };
+}
+
+/// the level of the backend, `log::Level` or `tracing::Level`
+#[allow(unused_macros)]
+#[cfg(all(feature = "log-crate", not(feature = "tracing")))]
+macro_rules! log_level {
+    ("error") => { ::log::Level::Error };
+    ("warn") => { ::log::Level::Warn };
+    ("info") => { ::log::Level::Info };
+    ("debug") => { ::log::Level::Debug };
+    ("trace") => { ::log::Level::Trace };
+}
+
+#[allow(unused_macros)]
+#[cfg(feature = "tracing")]
+macro_rules! log_level {
+    ("error") => { ::tracing::Level::ERROR };
+    ("warn") => { ::tracing::Level::WARN };
+    ("info") => { ::tracing::Level::INFO };
+    ("debug") => { ::tracing::Level::DEBUG };
+    ("trace") => { ::tracing::Level::TRACE };
+}
+
+/// A structured field of a log, as a value recorded by the backends.
+pub(crate) trait Field {
+    type Value;
+    fn field(&self)->Self::Value;
+    /// the value passed to a `LogSink`
+    fn value(&self)->LogValue<'_>;
+}
+
+/// The value of a structured field of a `LogRecord`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogValue<'a> {
+    /// e.g. the id of a task without an id
+    Null,
+    U64(u64),
+    Str(&'a str),
+}
+
+/// a task without an id has no value
+impl Field for crate::task::TaskId {
+    type Value = Option<u64>;
+    fn field(&self)->Option<u64> {
+        self.0.map(|id|id.get() as u64)
+    }
+    fn value(&self)->LogValue<'_> {
+        self.field().map_or(LogValue::Null, LogValue::U64)
+    }
+}
+
+impl Field for std::num::NonZeroUsize {
+    type Value = u64;
+    fn field(&self)->u64 {
+        self.get() as u64
+    }
+    fn value(&self)->LogValue<'_> {
+        LogValue::U64(self.field())
+    }
+}
+
+impl Field for usize {
+    type Value = u64;
+    fn field(&self)->u64 {
+        *self as u64
+    }
+    fn value(&self)->LogValue<'_> {
+        LogValue::U64(self.field())
+    }
+}
+
+impl Field for crate::task::Pi {
+    type Value = u64;
+    fn field(&self)->u64 {
+        self.0 as u64
+    }
+    fn value(&self)->LogValue<'_> {
+        LogValue::U64(self.field())
+    }
+}
+
+impl<'a> Field for &'a str {
+    type Value = &'a str;
+    fn field(&self)->&'a str {
+        self
+    }
+    fn value(&self)->LogValue<'_> {
+        LogValue::Str(self)
+    }
 }
 
 #[allow(unused_macros)]
@@ -128,9 +299,9 @@ fn test_format_by_mutstr() {
 }
 
 #[allow(unused)]
-#[cfg(any(
-    feature = "log-error",
-    feature = "log-warn",
+#[cfg(all(
+    any(feature = "log-error", feature = "log-warn"),
+    not(any(feature = "log-info", feature = "log-debug", feature = "log-trace")),
 ))]
 macro_rules! log_print {
     ($($args:tt)*) => {
@@ -139,9 +310,9 @@ macro_rules! log_print {
 }
 
 #[allow(unused)]
-#[cfg(not(any(
-    feature = "log-error",
-    feature = "log-warn",
+#[cfg(not(all(
+    any(feature = "log-error", feature = "log-warn"),
+    not(any(feature = "log-info", feature = "log-debug", feature = "log-trace")),
 )))]
 macro_rules! log_print {
     ($($args:tt)*) => {
@@ -218,6 +389,8 @@ macro_rules! myline {
     feature = "log-info",
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 )))]
 macro_rules! error {
     ($($args:tt)*) => {
@@ -232,6 +405,8 @@ macro_rules! error {
     feature = "log-info",
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 ))]
 macro_rules! error {
     ($($args:tt)*) => {
@@ -247,6 +422,8 @@ macro_rules! error {
     feature = "log-info",
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 )))]
 macro_rules! warn {
     ($($args:tt)*) => {
@@ -260,6 +437,8 @@ macro_rules! warn {
     feature = "log-info",
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 ))]
 macro_rules! warn {
     ($($args:tt)*) => {
@@ -274,6 +453,8 @@ macro_rules! warn {
     feature = "log-info",
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 )))]
 macro_rules! info {
     ($($args:tt)*) => {
@@ -286,6 +467,8 @@ macro_rules! info {
     feature = "log-info",
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 ))]
 macro_rules! info {
     ($($args:tt)*) => {
@@ -300,6 +483,8 @@ macro_rules! info {
 #[cfg(not(any(
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 )))]
 macro_rules! debug {
     ($($args:tt)*) => {
@@ -311,6 +496,8 @@ macro_rules! debug {
 #[cfg(any(
     feature = "log-debug",
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 ))]
 macro_rules! debug {
     ($($args:tt)*) => {
@@ -324,6 +511,8 @@ macro_rules! debug {
 #[allow(unused_macros)]
 #[cfg(not(any(
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 )))]
 macro_rules! trace {
     ($($args:tt)*) => {
@@ -334,6 +523,8 @@ macro_rules! trace {
 #[allow(unused_macros)]
 #[cfg(any(
     feature = "log-trace",
+    feature = "log-crate",
+    feature = "tracing",
 ))]
 macro_rules! trace {
     ($($args:tt)*) => {
@@ -354,16 +545,21 @@ pub(crate) struct Timespan {
 
 pub(crate) fn uptime()->Timespan {
     let start = START_TIME.get_or_init(::std::time::Instant::now);
-    let e = start.elapsed();
-    let s = e.as_secs();
-    let day = (s/(3600*24)) as u32;
-    let s = s%(3600*24);
-    let hour = (s/3600) as u8;
-    let s = s%3600;
-    let min = (s/60) as u8;
-    let sec = (s%60) as u8;
-    let micro = e.subsec_micros() as u32;
-    Timespan { day, hour, min, sec, micro }
+    Timespan::from(start.elapsed())
+}
+
+impl From<Duration> for Timespan {
+    fn from(e:Duration)->Self {
+        let s = e.as_secs();
+        let day = (s/(3600*24)) as u32;
+        let s = s%(3600*24);
+        let hour = (s/3600) as u8;
+        let s = s%3600;
+        let min = (s/60) as u8;
+        let sec = (s%60) as u8;
+        let micro = e.subsec_micros();
+        Timespan { day, hour, min, sec, micro }
+    }
 }
 
 impl std::fmt::Display for Timespan {
@@ -386,6 +582,334 @@ impl std::fmt::Display for Timespan {
 }
 
 
+/// The env var of the runtime filter, e.g. `TASKORCH_LOG=debug,queue=trace`,
+/// a level alone is the default, and `module=level` is the level of a module and its submodules.
+pub(crate) const ENV_FILTER: &str = "TASKORCH_LOG";
+
+/// The runtime filter, within the level compiled in.
+struct Filter {
+    default: LogLevel,
+    /// the module paths without the leading `taskorch::`, e.g. `queue`
+    modules: Vec<(String,LogLevel)>,
+}
+
+impl Filter {
+    /// the level of the module, from the longest module path matched
+    fn level_of(&self, module:&str)->LogLevel {
+        let module = module.strip_prefix("taskorch::").unwrap_or(module);
+        self.modules.iter()
+            .filter(|(name,_)|module.strip_prefix(name.as_str()).is_some_and(|rest|rest.is_empty() || rest.starts_with("::")))
+            .max_by_key(|(name,_)|name.len())
+            .map_or(self.default, |(_,level)|*level)
+    }
+
+    /// applies the directives, those not understood are skipped
+    fn apply(&mut self, spec:&str) {
+        for directive in spec.split(',').map(str::trim).filter(|d|!d.is_empty()) {
+            match directive.split_once('=') {
+                None => if let Some(level) = LogLevel::parse(directive) {
+                    self.default = level.min(LEVEL);
+                },
+                Some((module,level)) => if let Some(level) = LogLevel::parse(level.trim()) {
+                    let module = module.trim();
+                    let module = module.strip_prefix("taskorch::").unwrap_or(module);
+                    self.modules.retain(|(name,_)|name != module);
+                    self.modules.push((module.to_string(), level.min(LEVEL)));
+                },
+            }
+        }
+    }
+
+    fn max(&self)->LogLevel {
+        self.modules.iter().map(|(_,level)|*level).fold(self.default, LogLevel::max)
+    }
+}
+
+static FILTER: RwLock<Filter> = RwLock::new(Filter { default: LEVEL, modules: Vec::new() });
+/// the most verbose level of the filter, to skip a log without locking
+static MAX: AtomicU8 = AtomicU8::new(LEVEL as u8);
+static FILTER_INIT: Once = Once::new();
+
+fn init_filter() {
+    FILTER_INIT.call_once(||{
+        if let Ok(spec) = std::env::var(ENV_FILTER) {
+            let mut filter = FILTER.write().unwrap();
+            filter.apply(&spec);
+            MAX.store(filter.max() as u8, Ordering::Relaxed);
+        }
+    });
+}
+
+/// sets the default level of the runtime filter, the levels of the modules are kept
+pub(crate) fn set_level(level:LogLevel) {
+    init_filter();
+    let mut filter = FILTER.write().unwrap();
+    filter.default = level.min(LEVEL);
+    MAX.store(filter.max() as u8, Ordering::Relaxed);
+}
+
+/// whether a log of the level in the module passes the runtime filter
+pub(crate) fn enabled(level:LogLevel, module:&str)->bool {
+    init_filter();
+    if level as u8 > MAX.load(Ordering::Relaxed) {
+        return false;
+    }
+    level <= FILTER.read().unwrap().level_of(module)
+}
+
+/// A log passed to a `LogSink`, displayed as the line printed by default without color.
+pub struct LogRecord<'a> {
+    pub level: LogLevel,
+    /// the module logging, e.g. `taskorch::queue`
+    pub module: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+    /// the time since the log starts, see the timestamp format
+    pub uptime: Duration,
+    /// the name of the thread, or its concise id if not named
+    pub thread: &'a str,
+    /// the structured fields, e.g. `("task_id", LogValue::U64(1))`, see `LogValue`
+    pub fields: &'a [(&'static str, LogValue<'a>)],
+    pub message: fmt::Arguments<'a>,
+}
+
+impl LogRecord<'_> {
+    fn prefix(&self)->String {
+        format!("[{} {:<5} {NAME} {}]", Timespan::from(self.uptime), self.level, self.thread)
+    }
+
+    /// the line with the prefix colored as the level, with the feature `log-color`
+    fn colored(&self)->String {
+        let color = match self.level {
+            LogLevel::Error => COLOR_ERROR,
+            LogLevel::Warn => COLOR_WARN,
+            LogLevel::Info => COLOR_INFO,
+            LogLevel::Debug => COLOR_DEBUG,
+            LogLevel::Trace | LogLevel::Off => COLOR_TRACE,
+        };
+        format!("{color}{}{COLOR_END} {}", self.prefix(), self.message)
+    }
+}
+
+/// the key of a field in the JSON, the cond index `pi` is `cond` and `type_name` is `type`
+fn json_key(key:&str)->&str {
+    match key {
+        "pi" => "cond",
+        "type_name" => "type",
+        key => key,
+    }
+}
+
+/// writes the string quoted and escaped as a JSON string
+pub(crate) fn json_str(out:&mut String, s:&str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = fmt::Write::write_fmt(out, format_args!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl LogRecord<'_> {
+    /// The record as one JSON object, with `ts_us`, `level`, `thread`, `file`, `line`,
+    /// the fields among `task_id`, `from_task`, `cond`, `qid` and `type`, and `msg` last.
+    ///
+    /// e.g. `{"ts_us":1042,"level":"debug","thread":"worker-1","file":"src/queue.rs","line":380,"task_id":7,"msg":"task#TaskId(7) is scheduled to run."}`
+    pub fn to_json(&self)->String {
+        use fmt::Write as _;
+        let mut out = String::with_capacity(256);
+        let _ = write!(out, "{{\"ts_us\":{},\"level\":\"{}\",\"thread\":", self.uptime.as_micros(), self.level);
+        json_str(&mut out, self.thread);
+        out.push_str(",\"file\":");
+        json_str(&mut out, self.file);
+        let _ = write!(out, ",\"line\":{}", self.line);
+        for (key,value) in self.fields {
+            let _ = write!(out, ",\"{}\":", json_key(key));
+            match value {
+                LogValue::Null => out.push_str("null"),
+                LogValue::U64(n) => { let _ = write!(out, "{n}"); }
+                LogValue::Str(s) => json_str(&mut out, s),
+            }
+        }
+        out.push_str(",\"msg\":");
+        match self.message.as_str() {
+            Some(msg) => json_str(&mut out, msg),
+            None => json_str(&mut out, &self.message.to_string()),
+        }
+        out.push('}');
+        out
+    }
+}
+
+impl Display for LogRecord<'_> {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+        write!(f, "{} {}", self.prefix(), self.message)
+    }
+}
+
+/// Takes the log when a level is enabled, instead of printing it to stdout, see `Pool::set_log_sink()`.
+///
+/// A closure `Fn(&LogRecord)` is a sink too.
+pub trait LogSink: Send + Sync {
+    fn write(&self, record:&LogRecord<'_>);
+}
+
+impl<F> LogSink for F
+where F: Fn(&LogRecord<'_>) + Send + Sync
+{
+    fn write(&self, record:&LogRecord<'_>) {
+        self(record)
+    }
+}
+
+/// Prints the log to stdout as by default.
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    fn write(&self, record:&LogRecord<'_>) {
+        println!("{}", record.colored());
+    }
+}
+
+/// Prints the log to stderr.
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write(&self, record:&LogRecord<'_>) {
+        eprintln!("{}", record.colored());
+    }
+}
+
+/// Writes the log line by line to a writer, e.g. a `File`, without color.
+pub struct WriteSink<W>(Mutex<W>);
+
+impl<W:Write+Send> WriteSink<W> {
+    pub fn new(w:W)->Self {
+        Self(Mutex::new(w))
+    }
+}
+
+impl<W:Write+Send> LogSink for WriteSink<W> {
+    fn write(&self, record:&LogRecord<'_>) {
+        let mut w = self.0.lock().unwrap();
+        let _ = writeln!(w, "{record}");
+    }
+}
+
+/// Keeps the last lines of the log in memory, e.g. to check the log in tests.
+///
+/// The clones share the lines, so a clone is kept to read what the installed one takes.
+/// # Example:
+/// ```rust
+/// # use taskorch::{Pool, RingBufferSink};
+/// let ring = RingBufferSink::new(100);
+/// Pool::new().set_log_sink(ring.clone());
+/// // ... run tasks
+/// for line in ring.lines() {
+///     println!("{line}");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RingBufferSink {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity:usize)->Self {
+        Self { lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// the lines kept, the oldest first
+    pub fn lines(&self)->Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lines.lock().unwrap().clear();
+    }
+}
+
+impl LogSink for RingBufferSink {
+    fn write(&self, record:&LogRecord<'_>) {
+        let mut lines = self.lines.lock().unwrap();
+        if self.capacity == 0 {
+            return;
+        }
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(record.to_string());
+    }
+}
+
+/// Writes the log as JSON lines to a writer, one object per log, see `LogRecord::to_json()`.
+///
+/// With the feature `log-json`, the log is printed as JSON lines by default.
+/// # Example:
+/// ```rust
+/// # use taskorch::{JsonSink, Pool};
+/// Pool::new().set_log_sink(JsonSink::new(std::io::stderr()));
+/// ```
+pub struct JsonSink<W>(Mutex<W>);
+
+impl<W:Write+Send> JsonSink<W> {
+    pub fn new(w:W)->Self {
+        Self(Mutex::new(w))
+    }
+}
+
+impl<W:Write+Send> LogSink for JsonSink<W> {
+    fn write(&self, record:&LogRecord<'_>) {
+        let line = record.to_json();
+        let mut w = self.0.lock().unwrap();
+        let _ = writeln!(w, "{line}");
+    }
+}
+
+static SINK: RwLock<Option<Arc<dyn LogSink>>> = RwLock::new(None);
+
+/// the sink installed, None to print as by default
+#[cfg(not(feature = "log-json"))]
+pub(crate) fn sink()->Option<Arc<dyn LogSink>> {
+    SINK.read().unwrap().clone()
+}
+
+/// the sink installed, or the JSON lines printed as by default
+#[cfg(feature = "log-json")]
+pub(crate) fn sink()->Option<Arc<dyn LogSink>> {
+    static JSON: OnceLock<Arc<dyn LogSink>> = OnceLock::new();
+    let sink = SINK.read().unwrap().clone();
+    sink.or_else(||Some(JSON.get_or_init(||match LEVEL <= LogLevel::Warn {
+        true => Arc::new(JsonSink::new(std::io::stderr())),
+        false => Arc::new(JsonSink::new(std::io::stdout())),
+    }).clone()))
+}
+
+pub(crate) fn set_sink(sink:Arc<dyn LogSink>) {
+    *SINK.write().unwrap() = Some(sink);
+}
+
+/// passes the log to the sink, on the current thread
+pub(crate) fn write(sink:&dyn LogSink, level:LogLevel, module:&'static str, file:&'static str, line:u32,
+    fields:&[(&'static str, LogValue<'_>)], message:fmt::Arguments<'_>)
+{
+    let mut buff = [0u8;32];
+    let (head,tail) = format_concise_current_threadid(&mut buff);
+    let thread = format!("{head}{tail}");
+    let uptime = START_TIME.get_or_init(Instant::now).elapsed();
+    sink.write(&LogRecord { level, module, file, line, uptime, thread: &thread, fields, message });
+}
+
 #[allow(unused_macros)]
 macro_rules! sleep_millis {
     () => {
@@ -407,6 +931,151 @@ fn test_log() {
     trace!("message trace");
 }
 
+#[test]
+fn test_log_filter() {
+    let mut filter = Filter { default: LogLevel::Trace, modules: Vec::new() };
+    filter.apply("debug, queue=trace,taskorch::rate=off,submitter=noisy");
+    assert_eq!(filter.default, LogLevel::Debug.min(LEVEL));
+    assert_eq!(filter.level_of("taskorch::queue"), LogLevel::Trace.min(LEVEL));
+    assert_eq!(filter.level_of("taskorch::queue::inner"), LogLevel::Trace.min(LEVEL));
+    assert_eq!(filter.level_of("taskorch::queued"), LogLevel::Debug.min(LEVEL));
+    assert_eq!(filter.level_of("taskorch::rate"), LogLevel::Off);
+    assert_eq!(filter.level_of("taskorch::submitter"), LogLevel::Debug.min(LEVEL));
+    assert_eq!(filter.max(), LogLevel::Trace.min(LEVEL));
+}
+
+#[test]
+fn test_ring_buffer_sink() {
+    let ring = RingBufferSink::new(2);
+    for i in 0..3 {
+        write(&ring, LogLevel::Info, module_path!(), file!(), line!(), &[], format_args!("message {i}"));
+    }
+    let lines = ring.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" message 1") && lines[1].ends_with(" message 2"), "{lines:?}");
+    assert!(lines[0].starts_with('[') && lines[0].contains(" info  taskorch "), "{lines:?}");
+}
+
+#[test]
+fn test_to_json() {
+    let fields = [("task_id", LogValue::U64(7)), ("from_task", LogValue::Null), ("pi", LogValue::U64(1)), ("type_name", LogValue::Str("i32"))];
+    let message = "got \"7\"\n";
+    let record = LogRecord {
+        level: LogLevel::Debug,
+        module: module_path!(),
+        file: "src/queue.rs",
+        line: 42,
+        uptime: Duration::from_micros(1042),
+        thread: "worker-1",
+        fields: &fields,
+        message: format_args!("{message}"),
+    };
+    assert_eq!(record.to_json(),
+        "{\"ts_us\":1042,\"level\":\"debug\",\"thread\":\"worker-1\",\"file\":\"src/queue.rs\",\"line\":42,\
+        \"task_id\":7,\"from_task\":null,\"cond\":1,\"type\":\"i32\",\"msg\":\"got \\\"7\\\"\\n\"}");
+}
+
+#[test]
+fn test_log_fields() {
+    let _taskid = crate::task::TaskId::from(3);
+    let _pi = crate::task::Pi::PI1;
+    error!(task_id=_taskid, pi=_pi, type_name="i32"; "message error of task#{_taskid:?}.cond#{_pi:?}");
+    debug!(task_id=_taskid, qid=1usize; "message debug of task#{_taskid:?}");
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing() {
+    use std::{fmt::{Debug, Write}, sync::{Arc, atomic::{AtomicU64, Ordering}}};
+    use tracing::{field::{Field, Visit}, span, Event, Metadata, Subscriber};
+    use crate::{Pool, Queue, TaskBuildNew, TaskId};
+
+    struct Fields<'a>(&'a mut String);
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field:&Field, value:&dyn Debug) {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+    struct Collect(Arc<Mutex<Vec<String>>>, AtomicU64);
+    impl Subscriber for Collect {
+        fn enabled(&self, _:&Metadata<'_>)->bool { true }
+        fn new_span(&self, span:&span::Attributes<'_>)->span::Id {
+            let mut line = format!("span {}", span.metadata().name());
+            span.record(&mut Fields(&mut line));
+            self.0.lock().unwrap().push(line);
+            span::Id::from_u64(self.1.fetch_add(1, Ordering::Relaxed))
+        }
+        fn record(&self, _:&span::Id, _:&span::Record<'_>) {}
+        fn record_follows_from(&self, _:&span::Id, _:&span::Id) {}
+        fn event(&self, event:&Event<'_>) {
+            let mut line = format!("{} {}", event.metadata().level(), event.metadata().target());
+            event.record(&mut Fields(&mut line));
+            self.0.lock().unwrap().push(line);
+        }
+        fn enter(&self, _:&span::Id) {}
+        fn exit(&self, _:&span::Id) {}
+    }
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::set_global_default(Collect(lines.clone(), AtomicU64::new(1))).unwrap();
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    submitter.submit((||{}, TaskId::from(700)).into_task()).unwrap();
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.spawn_thread_for(qid);
+    pool.join();
+
+    let lines = lines.lock().unwrap();
+    let lines: Vec<&str> = lines.iter().filter(|l|l.contains("task_id=700")).map(String::as_str).collect();
+    assert_eq!(lines, [
+        "DEBUG taskorch message=task#TaskId(700) added into Q#1 task_id=700 qid=1",
+        "span task task_id=700 qid=1 kind=Normal",
+        "DEBUG taskorch message=task#TaskId(700) is scheduled to run. task_id=700",
+    ]);
+}
+
+#[cfg(all(feature = "log-crate", not(feature = "tracing")))]
+#[test]
+fn test_log_crate() {
+    use std::fmt::Write;
+    use log::kv::{Error, Key, Value, VisitSource};
+    use crate::{Pool, Queue, TaskBuildNew, TaskId};
+
+    struct Fields<'a>(&'a mut String);
+    impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+        fn visit_pair(&mut self, key:Key<'kvs>, value:Value<'kvs>)->Result<(), Error> {
+            let _ = write!(self.0, " {key}={value}");
+            Ok(())
+        }
+    }
+    struct Collect(Mutex<Vec<String>>);
+    impl log::Log for Collect {
+        fn enabled(&self, _:&log::Metadata<'_>)->bool { true }
+        fn log(&self, record:&log::Record<'_>) {
+            let mut line = format!("{} {} {}", record.level(), record.target(), record.args());
+            let _ = record.key_values().visit(&mut Fields(&mut line));
+            self.0.lock().unwrap().push(line);
+        }
+        fn flush(&self) {}
+    }
+
+    let logger: &'static Collect = Box::leak(Box::new(Collect(Mutex::new(Vec::new()))));
+    log::set_logger(logger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    submitter.submit((||{}, TaskId::from(700)).into_task()).unwrap();
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.spawn_thread_for(qid);
+    pool.join();
+
+    let lines = logger.0.lock().unwrap();
+    assert!(lines.iter().any(|l|l == "DEBUG taskorch task#TaskId(700) added into Q#1 task_id=700 qid=1"), "{lines:?}");
+    assert!(lines.iter().any(|l|l == "DEBUG taskorch task#TaskId(700) is scheduled to run. task_id=700"), "{lines:?}");
+}
+
 fn format_threadid(buf:&mut[u8;32])->usize {
     use std::io::{Cursor,Write};
     let mut cursor = Cursor::new(&mut buf[..]);
@@ -446,10 +1115,26 @@ fn concise_threadid(buf:&[u8;32], len:usize)->(&str,&str) {
 type ThreadIdBuf = [u8;32];
 #[allow(dead_code)]
 pub(crate) fn format_concise_current_threadid(buff:&mut ThreadIdBuf)->(&str,&str) {
+    // a named thread, e.g. a worker spawned with a WorkerConfig, shows its name instead
+    if let Some(len) = format_threadname(buff) {
+        return (from_utf8(&buff[..len]).unwrap_or(""), "");
+    }
     let len = format_threadid(buff);
     concise_threadid(buff, len)
 }
 
+/// copies the name of the current thread, cut at a char boundary if too long
+fn format_threadname(buf:&mut ThreadIdBuf)->Option<usize> {
+    let thread = ::std::thread::current();
+    let name = thread.name()?;
+    let mut len = name.len().min(buf.len());
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+    Some(len)
+}
+
 
 #[test]
 fn test_format_threadid_by_cursor() {
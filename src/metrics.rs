@@ -0,0 +1,593 @@
+//! ## metrics module
+//!
+//! Lock-free counters and histograms of the queues and the workers, see `Pool::metrics()`.
+//!
+//! With the feature `metrics` disabled, the recording functions are empty and nothing is kept.
+
+#[cfg(feature = "metrics")]
+pub use imp::{HistogramSnapshot, PoolMetrics, QueueMetricsSnapshot, TagMetricsSnapshot, WorkerMetricsSnapshot};
+pub(crate) use imp::{QueueMetrics, Registry, Worker};
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use std::{
+        cell::RefCell,
+        sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex, OnceLock, RwLock},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::task::TaskAttr;
+
+    /// each power of two of microseconds is split into 2^SUB_BITS linear sub-buckets
+    const SUB_BITS: u32 = 3;
+    const SUBS: usize = 1 << SUB_BITS;
+    const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUBS;
+
+    /// An HDR-style histogram of durations in microseconds, with a relative error of 1/8 at most.
+    pub(crate) struct Histogram {
+        counts: [AtomicU64; BUCKETS],
+        sum: AtomicU64,
+        max: AtomicU64,
+    }
+
+    impl Default for Histogram {
+        fn default()->Self {
+            Self {
+                counts: std::array::from_fn(|_|AtomicU64::new(0)),
+                sum: AtomicU64::new(0),
+                max: AtomicU64::new(0),
+            }
+        }
+    }
+
+    fn bucket_of(micros:u64)->usize {
+        if micros < SUBS as u64 {
+            return micros as usize;
+        }
+        let exp = 63 - micros.leading_zeros();
+        let sub = (micros >> (exp - SUB_BITS)) as usize & (SUBS - 1);
+        (exp - SUB_BITS + 1) as usize * SUBS + sub
+    }
+
+    /// the range of the microseconds falling into the bucket, the high end is exclusive
+    fn bounds_of(bucket:usize)->(u64,u64) {
+        if bucket < SUBS {
+            return (bucket as u64, bucket as u64 + 1);
+        }
+        let exp = (bucket / SUBS) as u32 + SUB_BITS - 1;
+        let low = ((SUBS + bucket % SUBS) as u64) << (exp - SUB_BITS);
+        (low, low.saturating_add(1 << (exp - SUB_BITS)))
+    }
+
+    impl Histogram {
+        pub(crate) fn record(&self, d:Duration) {
+            let micros = d.as_micros().min(u64::MAX as u128) as u64;
+            self.counts[bucket_of(micros)].fetch_add(1, Ordering::Relaxed);
+            self.sum.fetch_add(micros, Ordering::Relaxed);
+            self.max.fetch_max(micros, Ordering::Relaxed);
+        }
+
+        pub(crate) fn snapshot(&self)->HistogramSnapshot {
+            let buckets: Vec<(u64,u64,u64)> = self.counts.iter().enumerate()
+                .filter_map(|(i,count)|{
+                    let count = count.load(Ordering::Relaxed);
+                    let (low,high) = bounds_of(i);
+                    (count > 0).then_some((low,high,count))
+                })
+                .collect();
+            HistogramSnapshot {
+                count: buckets.iter().map(|(_,_,count)|count).sum(),
+                sum: Duration::from_micros(self.sum.load(Ordering::Relaxed)),
+                max: Duration::from_micros(self.max.load(Ordering::Relaxed)),
+                buckets,
+            }
+        }
+    }
+
+    /// The durations recorded by a histogram at the moment of `Pool::metrics()`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct HistogramSnapshot {
+        pub count: u64,
+        pub sum: Duration,
+        pub max: Duration,
+        /// the non-empty buckets, (low, high, count) in microseconds, the high end is exclusive
+        buckets: Vec<(u64,u64,u64)>,
+    }
+
+    impl HistogramSnapshot {
+        /// adds the durations of `other` into this one
+        pub fn merge(&mut self, other:&HistogramSnapshot) {
+            self.count += other.count;
+            self.sum += other.sum;
+            self.max = self.max.max(other.max);
+            for bucket in other.buckets.iter() {
+                match self.buckets.binary_search_by_key(&bucket.0, |b|b.0) {
+                    Ok(i) => self.buckets[i].2 += bucket.2,
+                    Err(i) => self.buckets.insert(i, *bucket),
+                }
+            }
+        }
+
+        pub fn mean(&self)->Duration {
+            match self.count {
+                0 => Duration::ZERO,
+                n => self.sum / n.min(u32::MAX as u64) as u32,
+            }
+        }
+
+        /// The duration which the fraction `q` (0.0 to 1.0) of the recorded ones do not exceed,
+        /// precise to the bucket holding it.
+        pub fn percentile(&self, q:f64)->Duration {
+            let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+            let mut seen = 0;
+            for (_,high,count) in self.buckets.iter() {
+                seen += count;
+                if seen >= rank {
+                    return Duration::from_micros(high - 1).min(self.max);
+                }
+            }
+            self.max
+        }
+
+        /// The non-empty buckets, each as (from, until, count), `until` is exclusive.
+        pub fn buckets(&self)->impl Iterator<Item=(Duration,Duration,u64)> + '_ {
+            self.buckets.iter().map(|(low,high,count)|
+                (Duration::from_micros(*low), Duration::from_micros(*high), *count))
+        }
+    }
+
+    /// The counters of the tasks with the same tag in a queue.
+    #[derive(Default)]
+    struct Counters {
+        submitted: AtomicU64,
+        completed: AtomicU64,
+        panicked: AtomicU64,
+        cancelled: AtomicU64,
+        wait: Histogram,
+        run: Histogram,
+    }
+
+    impl Counters {
+        fn snapshot(&self, tag:&'static str)->TagMetricsSnapshot {
+            TagMetricsSnapshot {
+                tag,
+                submitted: self.submitted.load(Ordering::Relaxed),
+                completed: self.completed.load(Ordering::Relaxed),
+                panicked: self.panicked.load(Ordering::Relaxed),
+                cancelled: self.cancelled.load(Ordering::Relaxed),
+                wait: self.wait.snapshot(),
+                run: self.run.snapshot(),
+            }
+        }
+    }
+
+    /// the tags per chunk of `TagList`
+    const TAG_SLOTS: usize = 16;
+
+    /// An append-only list of the counters per tag, looked up and extended without any lock.
+    struct TagList {
+        slots: [OnceLock<Box<(&'static str,Counters)>>; TAG_SLOTS],
+        /// the next chunk, once the slots are all taken
+        more: OnceLock<Box<TagList>>,
+    }
+
+    impl Default for TagList {
+        fn default()->Self {
+            Self { slots: [const { OnceLock::new() }; TAG_SLOTS], more: OnceLock::new() }
+        }
+    }
+
+    impl TagList {
+        /// the counters of the tag, added into the first free slot the first time
+        fn get(&self, tag:&'static str)->&Counters {
+            let mut list = self;
+            loop {
+                for slot in list.slots.iter() {
+                    // a slot taken by another tag meanwhile is skipped
+                    let entry = slot.get_or_init(||Box::new((tag,Counters::default())));
+                    if entry.0 == tag {
+                        return &entry.1;
+                    }
+                }
+                list = list.more.get_or_init(Default::default);
+            }
+        }
+
+        fn iter(&self)->impl Iterator<Item=&(&'static str,Counters)> {
+            let mut list = Some(self);
+            std::iter::from_fn(move||{
+                let chunk = list?;
+                list = chunk.more.get().map(|more|&**more);
+                Some(chunk.slots.iter().filter_map(|slot|slot.get().map(|entry|&**entry)))
+            }).flatten()
+        }
+    }
+
+    /// The counters of a queue, kept by the queue itself.
+    ///
+    /// The counters are atomics, the tagged ones are found by a scan of the tags of the queue.
+    #[derive(Default)]
+    pub(crate) struct QueueMetrics {
+        depth: AtomicUsize,
+        untagged: Counters,
+        tagged: TagList,
+    }
+
+    impl QueueMetrics {
+        fn with(&self, tag:Option<&'static str>, f:impl FnOnce(&Counters)) {
+            match tag {
+                Some(tag) => f(self.tagged.get(tag)),
+                None => f(&self.untagged),
+            }
+        }
+
+        pub(crate) fn submitted(&self, tag:Option<&'static str>) {
+            self.with(tag, |c|{c.submitted.fetch_add(1, Ordering::Relaxed);});
+        }
+        pub(crate) fn completed(&self, tag:Option<&'static str>) {
+            self.with(tag, |c|{c.completed.fetch_add(1, Ordering::Relaxed);});
+        }
+        pub(crate) fn panicked(&self, tag:Option<&'static str>) {
+            self.with(tag, |c|{c.panicked.fetch_add(1, Ordering::Relaxed);});
+        }
+        /// timed out, or dropped after its deadline
+        pub(crate) fn cancelled(&self, tag:Option<&'static str>) {
+            self.with(tag, |c|{c.cancelled.fetch_add(1, Ordering::Relaxed);});
+        }
+        /// a task is added into the queue, `fresh` unless it is put back
+        pub(crate) fn pushed(&self, attr:&mut TaskAttr, fresh:bool) {
+            self.depth.fetch_add(1, Ordering::Relaxed);
+            if fresh {
+                attr.queued_at = Some(Instant::now());
+            }
+        }
+        /// `n` tasks are taken out of the queue
+        pub(crate) fn popped(&self, n:usize) {
+            self.depth.fetch_sub(n, Ordering::Relaxed);
+        }
+        /// the task is about to run
+        pub(crate) fn dequeued(&self, attr:&mut TaskAttr) {
+            if let Some(at) = attr.queued_at.take() {
+                self.with(attr.tag, |c|c.wait.record(at.elapsed()));
+            }
+        }
+        pub(crate) fn ran(&self, tag:Option<&'static str>, elapsed:Duration) {
+            self.with(tag, |c|c.run.record(elapsed));
+        }
+
+        fn snapshot(&self, qid:usize)->QueueMetricsSnapshot {
+            let mut tags: Vec<TagMetricsSnapshot> = self.tagged.iter()
+                .map(|(tag,counters)|counters.snapshot(tag))
+                .collect();
+            tags.sort_by_key(|t|t.tag);
+            let untagged = self.untagged.snapshot("");
+            let mut all = untagged.clone();
+            for t in tags.iter() {
+                all.submitted += t.submitted;
+                all.completed += t.completed;
+                all.panicked += t.panicked;
+                all.cancelled += t.cancelled;
+                all.wait.merge(&t.wait);
+                all.run.merge(&t.run);
+            }
+            QueueMetricsSnapshot {
+                qid,
+                submitted: all.submitted,
+                completed: all.completed,
+                panicked: all.panicked,
+                cancelled: all.cancelled,
+                depth: self.depth.load(Ordering::Relaxed),
+                wait: all.wait,
+                run: all.run,
+                untagged,
+                tags,
+            }
+        }
+    }
+
+    /// The counters of a queue at the moment of `Pool::metrics()`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct QueueMetricsSnapshot {
+        pub qid: usize,
+        pub submitted: u64,
+        pub completed: u64,
+        pub panicked: u64,
+        /// timed out, or dropped after the deadline
+        pub cancelled: u64,
+        /// the count of the tasks ready in the queue
+        pub depth: usize,
+        /// how long the tasks stay ready in the queue before a worker pops them
+        pub wait: HistogramSnapshot,
+        /// how long the tasks run
+        pub run: HistogramSnapshot,
+        /// the share of the tasks without a tag, whose `tag` is empty
+        pub untagged: TagMetricsSnapshot,
+        /// the share of each tag, see `TaskBuild::tag()`
+        pub tags: Vec<TagMetricsSnapshot>,
+    }
+
+    /// The counters of the tasks with the same tag in a queue.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct TagMetricsSnapshot {
+        pub tag: &'static str,
+        pub submitted: u64,
+        pub completed: u64,
+        pub panicked: u64,
+        pub cancelled: u64,
+        pub wait: HistogramSnapshot,
+        pub run: HistogramSnapshot,
+    }
+
+    /// The metrics of the queues and the workers of a pool,
+    /// readable without the pool, e.g. by the metrics listener.
+    type Queues = RwLock<Vec<(usize,Arc<QueueMetrics>)>>;
+
+    #[derive(Clone, Default)]
+    pub(crate) struct Registry {
+        queues: Arc<Queues>,
+        workers: Workers,
+        /// the count of the waiting tasks
+        waiting: Option<Arc<dyn Fn()->usize + Send + Sync>>,
+    }
+
+    impl Registry {
+        pub(crate) fn new(waiting:impl Fn()->usize + Send + Sync + 'static)->Self {
+            Self { waiting: Some(Arc::new(waiting)), ..Default::default() }
+        }
+
+        pub(crate) fn register(&self, qid:usize, metrics:Arc<QueueMetrics>) {
+            self.queues.write().unwrap().push((qid,metrics));
+        }
+
+        pub(crate) fn workers(&self)->&Workers {
+            &self.workers
+        }
+
+        pub(crate) fn snapshot(&self)->PoolMetrics {
+            let mut queues: Vec<QueueMetricsSnapshot> = self.queues.read().unwrap().iter()
+                .map(|(qid,metrics)|metrics.snapshot(*qid))
+                .collect();
+            queues.sort_by_key(|q|q.qid);
+            PoolMetrics {
+                queues,
+                waiting: self.waiting.as_ref().map_or(0, |waiting|waiting()),
+                workers: self.workers.snapshot(),
+            }
+        }
+    }
+
+    struct WorkerState {
+        name: String,
+        started: Instant,
+        tasks: AtomicU64,
+        busy: AtomicU64,
+        /// the nanos from the start to the stop, 0 while running
+        lifetime: AtomicU64,
+        running: AtomicBool,
+    }
+
+    /// The workers of a pool, each registers itself when it starts.
+    #[derive(Clone, Default)]
+    pub(crate) struct Workers(Arc<Mutex<Vec<Arc<WorkerState>>>>);
+
+    impl Workers {
+        pub(crate) fn snapshot(&self)->Vec<WorkerMetricsSnapshot> {
+            self.0.lock().unwrap().iter().map(|state|{
+                let running = state.running.load(Ordering::Acquire);
+                let lifetime = match running {
+                    true => state.started.elapsed(),
+                    false => Duration::from_nanos(state.lifetime.load(Ordering::Relaxed)),
+                };
+                let busy = Duration::from_nanos(state.busy.load(Ordering::Relaxed));
+                WorkerMetricsSnapshot {
+                    name: state.name.clone(),
+                    tasks: state.tasks.load(Ordering::Relaxed),
+                    busy,
+                    idle: lifetime.saturating_sub(busy),
+                    running,
+                }
+            }).collect()
+        }
+    }
+
+    /// The time a worker spends at the moment of `Pool::metrics()`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct WorkerMetricsSnapshot {
+        /// the thread name, or the thread id if unnamed
+        pub name: String,
+        /// the count of the tasks run
+        pub tasks: u64,
+        /// the time running tasks and passing on the results
+        pub busy: Duration,
+        /// the time waiting for tasks
+        pub idle: Duration,
+        /// false once the worker has exited
+        pub running: bool,
+    }
+
+    /// The metrics of a pool at a moment.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct PoolMetrics {
+        /// in the order of qid
+        pub queues: Vec<QueueMetricsSnapshot>,
+        /// the count of the conditional tasks waiting for their conds
+        pub waiting: usize,
+        pub workers: Vec<WorkerMetricsSnapshot>,
+    }
+
+    thread_local! {
+        static CURRENT: RefCell<Option<Arc<WorkerState>>> = const { RefCell::new(None) };
+    }
+
+    /// The current thread as a worker of the pool, from its start until dropped.
+    pub(crate) struct Worker;
+
+    impl Worker {
+        pub(crate) fn start(workers:Option<&Workers>)->Self {
+            let Some(workers) = workers else {
+                return Self;
+            };
+            let thread = thread::current();
+            let state = Arc::new(WorkerState {
+                name: thread.name().map_or_else(||format!("{:?}", thread.id()), String::from),
+                started: Instant::now(),
+                tasks: AtomicU64::new(0),
+                busy: AtomicU64::new(0),
+                lifetime: AtomicU64::new(0),
+                running: AtomicBool::new(true),
+            });
+            workers.0.lock().unwrap().push(state.clone());
+            CURRENT.set(Some(state));
+            Self
+        }
+
+        /// the current worker has run a task
+        pub(crate) fn busy(elapsed:Duration) {
+            CURRENT.with_borrow(|state|{
+                if let Some(state) = state {
+                    state.tasks.fetch_add(1, Ordering::Relaxed);
+                    state.busy.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+                }
+            });
+        }
+    }
+
+    impl Drop for Worker {
+        fn drop(&mut self) {
+            if let Some(state) = CURRENT.take() {
+                state.lifetime.store(state.started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                state.running.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    #[test]
+    fn test_histogram() {
+        for micros in [0, 7, 8, 9, 15, 16, 1000, 123_456_789, u64::MAX] {
+            let (low,high) = bounds_of(bucket_of(micros));
+            assert!(low <= micros && (micros < high || high == u64::MAX), "{micros} {low} {high}");
+        }
+        let h = Histogram::default();
+        for ms in 1..=100 {
+            h.record(Duration::from_millis(ms));
+        }
+        let s = h.snapshot();
+        assert_eq!(s.count, 100);
+        assert_eq!(s.max, Duration::from_millis(100));
+        let p50 = s.percentile(0.5);
+        assert!(p50 >= Duration::from_millis(50) && p50 <= Duration::from_millis(57), "{p50:?}");
+        assert_eq!(s.percentile(1.0), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_tag_list() {
+        const TAGS: [&str; 40] = ["t00","t01","t02","t03","t04","t05","t06","t07","t08","t09",
+            "t10","t11","t12","t13","t14","t15","t16","t17","t18","t19",
+            "t20","t21","t22","t23","t24","t25","t26","t27","t28","t29",
+            "t30","t31","t32","t33","t34","t35","t36","t37","t38","t39"];
+        let metrics = QueueMetrics::default();
+        // the tags raced by several threads, each tag counted once per thread
+        std::thread::scope(|s|for i in 0..4 {
+            let metrics = &metrics;
+            s.spawn(move||for k in 0..TAGS.len() {
+                metrics.submitted(Some(TAGS[(k + i * 7) % TAGS.len()]));
+            });
+        });
+        metrics.submitted(None);
+        let snapshot = metrics.snapshot(0);
+        assert_eq!(snapshot.submitted, 4 * TAGS.len() as u64 + 1);
+        assert_eq!(snapshot.tags.iter().map(|t|t.tag).collect::<Vec<_>>(), TAGS);
+        assert!(snapshot.tags.iter().all(|t|t.submitted == 4));
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::task::TaskAttr;
+
+    #[derive(Default)]
+    pub(crate) struct QueueMetrics {}
+
+    impl QueueMetrics {
+        pub(crate) fn submitted(&self, _tag:Option<&'static str>) {}
+        pub(crate) fn completed(&self, _tag:Option<&'static str>) {}
+        pub(crate) fn panicked(&self, _tag:Option<&'static str>) {}
+        pub(crate) fn cancelled(&self, _tag:Option<&'static str>) {}
+        pub(crate) fn pushed(&self, _attr:&mut TaskAttr, _fresh:bool) {}
+        pub(crate) fn popped(&self, _n:usize) {}
+        pub(crate) fn dequeued(&self, _attr:&mut TaskAttr) {}
+        pub(crate) fn ran(&self, _tag:Option<&'static str>, _elapsed:Duration) {}
+    }
+
+    #[derive(Clone, Default)]
+    pub(crate) struct Workers {}
+
+    #[derive(Clone, Default)]
+    pub(crate) struct Registry {}
+
+    impl Registry {
+        pub(crate) fn new(_waiting:impl Fn()->usize)->Self {
+            Self {}
+        }
+        pub(crate) fn register(&self, _qid:usize, _metrics:Arc<QueueMetrics>) {}
+        pub(crate) fn workers(&self)->&Workers {
+            &Workers {}
+        }
+    }
+
+    pub(crate) struct Worker;
+
+    impl Worker {
+        pub(crate) fn start(_workers:Option<&Workers>)->Self {
+            Self
+        }
+        pub(crate) fn busy(_elapsed:Duration) {}
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+    use std::{sync::mpsc, time::{Duration, Instant}};
+    use crate::{Pool, Queue, TaskBuildNew, TaskId};
+
+    #[test]
+    fn test_pool_metrics() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        for _ in 0..3 {
+            let tx = tx.clone();
+            submitter.submit((move||{
+                std::thread::sleep(Duration::from_millis(2));
+                tx.send(()).unwrap();
+            }).into_task()).unwrap();
+        }
+        submitter.submit((||{}).into_task().deadline(Instant::now())).unwrap();
+        submitter.submit((|_:i32|{}, TaskId::from(300)).into_task()).unwrap();
+        let before = pool.metrics();
+        assert_eq!((before.queues[0].submitted,before.queues[0].depth,before.waiting), (5,4,1));
+
+        pool.spawn_thread_for(qid);
+        for _ in 0..3 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        // the exit task is run only after the others
+        while pool.metrics().workers.iter().all(|w|w.running) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let metrics = pool.metrics();
+        let q = &metrics.queues[0];
+        assert_eq!((q.completed,q.cancelled,q.panicked,q.depth), (4,1,0,0));
+        assert_eq!(q.run.count, 4);
+        assert!(q.run.max >= Duration::from_millis(2));
+        let w = &metrics.workers[0];
+        assert_eq!(w.tasks, 4);
+        assert!(w.busy >= Duration::from_millis(6), "{w:?}");
+        pool.join();
+    }
+}
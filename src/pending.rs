@@ -0,0 +1,73 @@
+//! ## pending module
+//!
+//! The introspection of a live pool: the conditional tasks waiting for their conds,
+//! see `Pool::pending_tasks()`, and the tasks ready in a queue, see `Queue::snapshot()`.
+
+use std::time::Instant;
+
+use crate::task::{Kind, Pi, TaskId};
+
+/// A conditional task waiting in the pool for its conds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingTask {
+    pub taskid: TaskId,
+    pub kind: Kind,
+    /// the queue which the task is submitted to
+    pub qid: usize,
+    /// the count of the conds
+    pub arity: usize,
+    /// the state of each cond, by its index
+    pub conds: Vec<CondState>,
+    pub submitted_at: Instant,
+}
+
+/// The state of a cond of a pending task.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CondState {
+    pub pi: Pi,
+    /// the type name of the cond, see `std::any::type_name()`
+    pub type_name: &'static str,
+    /// whether a value is delivered, or a default is set, see `TaskBuild::default_cond()`
+    pub filled: bool,
+    /// whether the cond is optional and still waits for a delivery, see `TaskBuild::optional_cond()`
+    pub optional: bool,
+}
+
+impl PendingTask {
+    /// the conds not filled yet
+    pub fn missing(&self)->impl Iterator<Item = &CondState> {
+        self.conds.iter().filter(|cond|!cond.filled)
+    }
+}
+
+#[test]
+fn test_pending_tasks() {
+    use crate::{Pool, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let before = Instant::now();
+    let t1 = submitter.submit((|_a:i32,_b:u8|{}, TaskId::from(1)).into_task().default_cond(Pi::PI1, 3u8)).unwrap();
+    submitter.submit((||5, TaskId::from(2)).into_task().to((t1,Pi::PI0).into())).unwrap();
+    submitter.submit((||{}, TaskId::from(3)).into_task()).unwrap();
+
+    let pending = pool.pending_tasks();
+    assert_eq!(pending.len(), 1);
+    let task = &pending[0];
+    assert_eq!((task.taskid, task.kind, task.qid, task.arity), (t1, Kind::Normal, qid, 2));
+    assert!(task.submitted_at >= before);
+    assert_eq!(task.conds, [
+        CondState { pi: Pi::PI0, type_name: "i32", filled: false, optional: false },
+        CondState { pi: Pi::PI1, type_name: "u8", filled: true, optional: false },
+    ]);
+    assert_eq!(task.missing().map(|cond|cond.pi).collect::<Vec<_>>(), [Pi::PI0]);
+    assert_eq!(pool.queue(qid).unwrap().snapshot(), [TaskId::from(2), TaskId::from(3)]);
+
+    // task#1 is released after the exit task, and left ready in the queue
+    let queue = pool.queue(qid).unwrap().clone();
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.spawn_thread_for(qid);
+    pool.join();
+    assert_eq!(queue.snapshot(), [t1]);
+}
@@ -0,0 +1,211 @@
+//! ## prometheus module
+//!
+//! Renders the metrics of a pool in the Prometheus text exposition format,
+//! and with the feature `prometheus-http`, serves them on a local port.
+
+use std::fmt::Write;
+
+use crate::metrics::{HistogramSnapshot, PoolMetrics, TagMetricsSnapshot};
+
+/// the upper bounds of the histogram buckets in seconds
+const LE: [f64; 16] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025,
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// the name, the help and the value of a task counter
+type Counter = (&'static str,&'static str,fn(&TagMetricsSnapshot)->u64);
+
+/// escapes a label value, see the exposition format
+fn escape(value:&str)->String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out:&mut String, name:&str, kind:&str, help:&str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn histogram(out:&mut String, name:&str, labels:&str, h:&HistogramSnapshot) {
+    let mut below = 0;
+    let mut buckets = h.buckets().peekable();
+    for le in LE {
+        // a bucket counts below `le` only if it is entirely below, so precise to the bucket
+        while let Some((_,until,count)) = buckets.peek() {
+            if until.as_secs_f64() > le {
+                break;
+            }
+            below += count;
+            buckets.next();
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{le}\"}} {below}");
+    }
+    let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", h.count);
+    let _ = writeln!(out, "{name}_sum{{{labels}}} {}", h.sum.as_secs_f64());
+    let _ = writeln!(out, "{name}_count{{{labels}}} {}", h.count);
+}
+
+impl PoolMetrics {
+    /// Renders the metrics in the Prometheus text exposition format (version 0.0.4).
+    ///
+    /// The task counters and histograms are labelled by `qid` and `tag`, the untagged tasks with `tag=""`,
+    /// so the sum over the tags is the total of a queue.
+    pub fn to_prometheus(&self)->String {
+        let mut out = String::new();
+        let rows: Vec<(usize,&TagMetricsSnapshot)> = self.queues.iter()
+            .flat_map(|q|std::iter::once(&q.untagged).chain(q.tags.iter()).map(move|t|(q.qid,t)))
+            .collect();
+        let labels = |qid:usize, t:&TagMetricsSnapshot|format!("qid=\"{qid}\",tag=\"{}\"", escape(t.tag));
+
+        let counters: [Counter; 4] = [
+            ("taskorch_tasks_submitted_total", "Tasks submitted.", |t|t.submitted),
+            ("taskorch_tasks_completed_total", "Tasks run to completion.", |t|t.completed),
+            ("taskorch_tasks_panicked_total", "Tasks panicked.", |t|t.panicked),
+            ("taskorch_tasks_cancelled_total", "Tasks timed out, or dropped after the deadline.", |t|t.cancelled),
+        ];
+        for (name,help,value) in counters {
+            header(&mut out, name, "counter", help);
+            for (qid,t) in rows.iter() {
+                let _ = writeln!(out, "{name}{{{}}} {}", labels(*qid,t), value(t));
+            }
+        }
+
+        header(&mut out, "taskorch_task_wait_seconds", "histogram", "Time the tasks stay ready in the queue.");
+        for (qid,t) in rows.iter() {
+            histogram(&mut out, "taskorch_task_wait_seconds", &labels(*qid,t), &t.wait);
+        }
+        header(&mut out, "taskorch_task_run_seconds", "histogram", "Time the tasks run.");
+        for (qid,t) in rows.iter() {
+            histogram(&mut out, "taskorch_task_run_seconds", &labels(*qid,t), &t.run);
+        }
+
+        header(&mut out, "taskorch_queue_depth", "gauge", "Tasks ready in the queue.");
+        for q in self.queues.iter() {
+            let _ = writeln!(out, "taskorch_queue_depth{{qid=\"{}\"}} {}", q.qid, q.depth);
+        }
+        header(&mut out, "taskorch_waiting_tasks", "gauge", "Conditional tasks waiting for their conds.");
+        let _ = writeln!(out, "taskorch_waiting_tasks {}", self.waiting);
+
+        header(&mut out, "taskorch_worker_tasks_total", "counter", "Tasks run by the worker.");
+        for w in self.workers.iter() {
+            let _ = writeln!(out, "taskorch_worker_tasks_total{{worker=\"{}\"}} {}", escape(&w.name), w.tasks);
+        }
+        header(&mut out, "taskorch_worker_busy_seconds_total", "counter", "Time the worker runs tasks.");
+        for w in self.workers.iter() {
+            let _ = writeln!(out, "taskorch_worker_busy_seconds_total{{worker=\"{}\"}} {}", escape(&w.name), w.busy.as_secs_f64());
+        }
+        header(&mut out, "taskorch_worker_idle_seconds_total", "counter", "Time the worker waits for tasks.");
+        for w in self.workers.iter() {
+            let _ = writeln!(out, "taskorch_worker_idle_seconds_total{{worker=\"{}\"}} {}", escape(&w.name), w.idle.as_secs_f64());
+        }
+        out
+    }
+}
+
+#[cfg(feature = "prometheus-http")]
+pub(crate) use http::serve;
+
+#[cfg(feature = "prometheus-http")]
+mod http {
+    use std::{
+        io::{self, BufRead, BufReader, Write},
+        net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+        thread,
+        time::Duration,
+    };
+
+    use crate::metrics::Registry;
+
+    /// Serves `GET /metrics` on the address, on a thread living as long as the process.
+    pub(crate) fn serve(registry:Registry, addr:impl ToSocketAddrs)->io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        thread::Builder::new()
+            .name("taskorch-metrics".into())
+            .spawn(move||{
+                info!("metrics listener starts on {local}.");
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    if let Err(_err) = respond(stream, &registry) {
+                        debug!("metrics request failed: {_err}");
+                    }
+                }
+            })?;
+        Ok(local)
+    }
+
+    fn respond(mut stream:TcpStream, registry:&Registry)->io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // the headers are not used, but read to the blank line before responding
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+        let path = request.split_whitespace().nth(1).unwrap_or("");
+        let (status,body) = match request.starts_with("GET ") && (path == "/metrics" || path == "/") {
+            true => ("200 OK", registry.snapshot().to_prometheus()),
+            false => ("404 Not Found", String::from("not found\n")),
+        };
+        write!(stream,
+            "HTTP/1.1 {status}\r\n\
+            Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\r\n{body}",
+            body.len())?;
+        stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::{Pool, Queue, TaskBuildNew};
+
+    fn pool()->Pool {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        submitter.submit((||{}).into_task()).unwrap();
+        submitter.submit((||{}).into_task().tag("a\"pi")).unwrap();
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.spawn_thread_for(qid);
+        while pool.metrics().workers.iter().all(|w|w.running) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        pool
+    }
+
+    #[test]
+    fn test_to_prometheus() {
+        let pool = pool();
+        let text = pool.metrics().to_prometheus();
+        assert!(text.contains("# TYPE taskorch_tasks_submitted_total counter\n"));
+        assert!(text.contains("taskorch_tasks_submitted_total{qid=\"1\",tag=\"\"} 2\n"), "{text}");
+        assert!(text.contains("taskorch_tasks_completed_total{qid=\"1\",tag=\"a\\\"pi\"} 1\n"), "{text}");
+        assert!(text.contains("taskorch_task_run_seconds_count{qid=\"1\",tag=\"\"} 2\n"), "{text}");
+        assert!(text.contains("taskorch_task_run_seconds_bucket{qid=\"1\",tag=\"\",le=\"+Inf\"} 2\n"), "{text}");
+        assert!(text.contains("taskorch_queue_depth{qid=\"1\"} 0\n"), "{text}");
+        assert!(text.contains("taskorch_waiting_tasks 0\n"), "{text}");
+        pool.join();
+    }
+
+    #[cfg(feature = "prometheus-http")]
+    #[test]
+    fn test_serve_metrics() {
+        use std::{io::{Read, Write}, net::TcpStream};
+        let pool = pool();
+        let addr = pool.serve_metrics("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("taskorch_tasks_submitted_total{qid=\"1\",tag=\"\"} 2\n"), "{response}");
+        pool.join();
+    }
+}
@@ -1,110 +1,629 @@
 use std::{
-    any::{type_name, Any}, collections::{HashMap, VecDeque}, fmt::Debug, num::NonZeroUsize, sync::{
-        atomic::{AtomicBool, Ordering}, Arc, Condvar, Mutex
-    }, thread
+    any::{type_name, Any}, collections::{HashMap, VecDeque}, fmt::Debug, num::NonZeroUsize,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe}, sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Condvar, Mutex, OnceLock, RwLock, Weak
+    }, io, thread, time::{Duration, Instant}
 };
 
-use crate::{task::{CondAddr, Kind, Task, TaskId}, Jhandle};
+use crate::{cancel::Watch, curry::CallParam, delivery::{DeadLetter, DeliveryError}, event::PoolEvent, future, journal::Journal, pending::{CondState, PendingTask}, stuck::{Delayed, MissingCond, StuckTask}, hook::{Hooks, Running, TaskInfo}, metrics::{QueueMetrics, Worker}, rate::TokenBucket, task::{CondAddr, CondTimeout, DeadlineMiss, DeadlineMissed, Kind, Pi, Task, TaskId}, timer, trace, Jhandle, Shared, WorkerConfig};
 
 // enum InsertError {
 //     /// task is must not be null
 //     TaskIdIsNull,
 // }
 
-type PostDo = dyn FnOnce(Box<dyn Any>) + Send;
+pub(crate) type PostDo = dyn FnOnce(Box<dyn Any>) + Send;
+pub(crate) type Queued = (Box<dyn Task+Send>,Box<PostDo>);
 // static  WHEN_NIL_COMED: Box<PostDo> = Box::new(|_|());
 
+struct QueueInner {
+    tasks: Mutex<VecDeque<Queued>>,
+    cond: Condvar,
+    /// how many tasks start at most per interval
+    rate: Option<Mutex<TokenBucket>>,
+    /// whether the tasks are ordered by deadline rather than FIFO
+    edf: bool,
+    /// the id of the queue and the state of the pool which the queue is inserted into
+    shared: OnceLock<(usize,Shared)>,
+    /// the weighted workers serving this queue among others
+    watchers: Mutex<Vec<Arc<Signal>>>,
+    metrics: Arc<QueueMetrics>,
+}
+
 /// A queue holding tasks awaiting scheduling by threads
 #[derive(Clone)]
-pub struct Queue(Arc<(Mutex<VecDeque<(Box<dyn Task+Send>,Box<PostDo>)>>,Condvar)>);
+pub struct Queue(Arc<QueueInner>);
 
 impl Queue {
     pub fn new()->Self {
-        Queue(Arc::new((Mutex::new(VecDeque::new()),Condvar::new())))
+        Self::build(None, false)
     }
 
-    pub(crate) fn add_boxtask(&self,task:Box<dyn Task+Send>, postdo: Box<PostDo>) {
-        let mut lock = self.0.0.lock().unwrap();
-        let is_empty = lock.is_empty();
-        lock.push_back((task,postdo));
-        if is_empty {
-            self.0.1.notify_one();
+    /// A queue whose workers start at most `n` tasks `per` interval, with a burst of `n` at most.
+    ///
+    /// The tasks over the rate stay in the queue, and the workers sleep until the next token comes.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use taskorch::Queue;
+    /// let queue = Queue::with_rate_limit(100, Duration::from_secs(1));
+    /// ```
+    pub fn with_rate_limit(n:u32, per:Duration)->Self {
+        Self::build(Some(TokenBucket::new(n, per)), false)
+    }
+
+    /// A queue running the task with the earliest deadline first, see `TaskBuild::deadline()`.
+    ///
+    /// The tasks without a deadline run after those with one, in FIFO order.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::Queue;
+    /// let queue = Queue::edf();
+    /// ```
+    pub fn edf()->Self {
+        Self::build(None, true)
+    }
+
+    fn build(rate:Option<TokenBucket>, edf:bool)->Self {
+        Queue(Arc::new(QueueInner {
+            tasks: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+            rate: rate.map(Mutex::new),
+            edf,
+            shared: OnceLock::new(),
+            watchers: Mutex::new(Vec::new()),
+            metrics: Arc::default(),
+        }))
+    }
+
+    pub(crate) fn add_boxtask(&self,mut task:Box<dyn Task+Send>, postdo: Box<PostDo>) {
+        self.0.metrics.pushed(task.attr_mut(), true);
+        let deadline = task.attr_mut().deadline;
+        let mut lock = self.0.tasks.lock().unwrap();
+        match deadline {
+            // after the tasks with the same deadline or an earlier one
+            Some(deadline) if self.0.edf => {
+                let i = lock.iter_mut()
+                    .position(|(task,_)|task.attr_mut().deadline.is_none_or(|d|d > deadline))
+                    .unwrap_or(lock.len());
+                lock.insert(i, (task,postdo));
+            }
+            _ => lock.push_back((task,postdo)),
+        }
+        drop(lock);
+        // each task wakes a worker, or else the idle ones may sleep with tasks in the queue
+        self.notify();
+    }
+
+    /// adds the task to be scheduled before all the others, e.g. a task back from parking.
+    /// In a queue ordered by deadline, the task goes before the others with the same deadline.
+    pub(crate) fn add_boxtask_front(&self,mut task:Box<dyn Task+Send>, postdo: Box<PostDo>) {
+        self.0.metrics.pushed(task.attr_mut(), false);
+        let deadline = task.attr_mut().deadline;
+        let mut lock = self.0.tasks.lock().unwrap();
+        if self.0.edf {
+            let i = lock.iter_mut()
+                .position(|(task,_)|match (task.attr_mut().deadline,deadline) {
+                    (Some(d),Some(deadline)) => d >= deadline,
+                    (Some(_),None) => false,
+                    (None,_) => true,
+                })
+                .unwrap_or(lock.len());
+            lock.insert(i, (task,postdo));
+        } else {
+            lock.push_front((task,postdo));
+        }
+        drop(lock);
+        self.notify();
+    }
+
+    fn notify(&self) {
+        self.0.cond.notify_one();
+        for watcher in self.0.watchers.lock().unwrap().iter() {
+            watcher.notify();
+        }
+    }
+
+    /// pops a task, Err(Some(wait)) if the queue is over its rate, Err(None) if empty.
+    fn try_pop(&self)->Result<Queued,Option<Duration>> {
+        let mut lock = self.0.tasks.lock().unwrap();
+        if lock.is_empty() {
+            return Err(None);
+        }
+        if let Some(rate) = &self.0.rate {
+            rate.lock().unwrap().try_take().map_err(Some)?;
+        }
+        let popped = lock.pop_front().ok_or(None);
+        self.0.metrics.popped(1);
+        popped
+    }
+
+    pub(crate) fn bind(&self, qid:usize, shared:&Shared) {
+        if self.0.shared.set((qid,shared.clone())).is_err() {
+            warn!("the queue has been inserted into another pool, the former is kept.");
+            return;
+        }
+        shared.metrics.register(qid, self.0.metrics.clone());
+        shared.activity.register(WeakQueue(Arc::downgrade(&self.0)));
+    }
+
+    /// adds the task into the queue after the delay, at the front or the back,
+    /// counted as delayed in the pool meanwhile.
+    pub(crate) fn add_later(&self, delay:Duration, task:Box<dyn Task+Send>, postdo:Box<PostDo>, front:bool) {
+        let delayed = self.delayed();
+        let queue = self.clone();
+        timer::schedule(delay, move||{
+            match front {
+                true => queue.add_boxtask_front(task, postdo),
+                false => queue.add_boxtask(task, postdo),
+            }
+            drop(delayed);
+        });
+    }
+
+    /// the first resource of which the task requires more than the limit of the pool, see `Limits::over_limit()`.
+    pub(crate) fn over_limit(&self, requires:&[(&'static str,usize)])->Option<(&'static str,usize,usize)> {
+        self.0.shared.get().and_then(|(_,shared)|shared.limits.over_limit(requires))
+    }
+
+    /// counts a task put aside as delayed in the pool, until dropped.
+    pub(crate) fn delayed(&self)->Option<Delayed> {
+        self.0.shared.get().map(|(_,shared)|shared.activity.delay())
+    }
+
+    pub(crate) fn metrics(&self)->&QueueMetrics {
+        &self.0.metrics
+    }
+
+    /// the hooks of the pool which the queue is inserted into
+    fn hooks(&self)->Option<Arc<Hooks>> {
+        self.0.shared.get().map(|(_,shared)|shared.hooks.clone())
+    }
+
+    /// passes the event to the listeners of the pool which the queue is inserted into
+    pub(crate) fn emit(&self, event:impl FnOnce(usize)->PoolEvent) {
+        if let Some((qid,shared)) = self.0.shared.get() {
+            shared.hooks.emit(||event(*qid));
         }
     }
 
+    /// The ids of the tasks ready in the queue, in the order they are going to be popped.
+    pub fn snapshot(&self)->Vec<TaskId> {
+        self.0.tasks.lock().unwrap().iter().map(|(task,_)|task.id()).collect()
+    }
+
+    /// passes each task ready in the queue to `f`, in the order they are going to be popped.
+    pub(crate) fn try_for_each<E>(&self, mut f:impl FnMut(&mut (dyn Task+Send))->Result<(),E>)->Result<(),E> {
+        self.0.tasks.lock().unwrap().iter_mut().try_for_each(|(task,_)|f(task.as_mut()))
+    }
+
+    /// takes the task at the position `i` in the order of popping, regardless of the rate limit.
+    pub(crate) fn take(&self, i:usize)->Option<Queued> {
+        let taken = self.0.tasks.lock().unwrap().remove(i);
+        self.0.metrics.popped(taken.iter().len());
+        taken
+    }
+
+    /// removes the tasks with the id from the queue, returns how many are removed.
+    pub(crate) fn discard(&self, taskid:TaskId)->usize {
+        let mut lock = self.0.tasks.lock().unwrap();
+        let len = lock.len();
+        lock.retain(|(task,_)|task.id() != taskid);
+        let discarded = len - lock.len();
+        self.0.metrics.popped(discarded);
+        discarded
+    }
+
     #[allow(dead_code)]
     pub(crate) fn pop(&self)->Option<(Box<dyn Task+Send>,Box<PostDo>)> {
-        self
-            .0
+        let popped = self
             .0
+            .tasks
             .lock()
             .unwrap()
-            .pop_front()
+            .pop_front();
+        self.0.metrics.popped(popped.iter().len());
+        popped
     }
     
     #[allow(dead_code)]
     fn clear(&self) {
-        self
-            .0
-            .0
-            .lock()
-            .unwrap()
-            .clear()
+        let mut lock = self.0.tasks.lock().unwrap();
+        self.0.metrics.popped(lock.len());
+        lock.clear()
     }
 
     pub fn len(&self)->usize {
         self
             .0
-            .0
+            .tasks
             .lock()
             .unwrap()
             .len()
     }
+
+    pub fn is_empty(&self)->bool {
+        self.len() == 0
+    }
 }
 
 pub fn spawn_thread(queue:&Queue)-> Jhandle {
     let quit_flag = Arc::<AtomicBool>::new(AtomicBool::new(false));
     let quit = quit_flag.clone();
-    let queue = queue.0.clone();
+    let queue = queue.clone();
+    let handle = thread::spawn(move||serve(queue, quit));
+    Jhandle(handle,quit_flag)
+}
+
+/// Spawns a worker for the queue with the thread options of `cfg`.
+///
+/// # Example:
+/// ```rust
+/// # use taskorch::{spawn_thread_with, Queue, WorkerConfig};
+/// let queue = Queue::new();
+/// let jhandle = spawn_thread_with(&queue, &WorkerConfig::new().name("io-{n}")).unwrap();
+/// ```
+pub fn spawn_thread_with(queue:&Queue, cfg:&WorkerConfig)-> io::Result<Jhandle> {
+    let quit_flag = Arc::<AtomicBool>::new(AtomicBool::new(false));
+    let quit = quit_flag.clone();
+    let qid = queue.0.shared.get().map(|(qid,_)|*qid);
+    let queue = queue.clone();
+    let handle = cfg.spawn(qid, move||serve(queue, quit))?;
+    Ok(Jhandle(handle,quit_flag))
+}
+
+/// the loop of a worker serving a single queue
+fn serve(queue:Queue, quit:Arc<AtomicBool>) {
+    let _running = Running::start(queue.hooks());
+    let _worker = Worker::start(queue.0.shared.get().map(|(_,shared)|shared.metrics.workers()));
+    warn!("starts ok.");
+    loop {
+        if quit.load(Ordering::Relaxed) {
+            warn!("Quit flag detected and prepare to exit.");
+            break;
+        }
+        
+        let mut m = queue.0.tasks.lock().unwrap();
+        let over_rate = match &queue.0.rate {
+            Some(rate) if !m.is_empty() => rate.lock().unwrap().try_take().err(),
+            _ => None,
+        };
+        if let Some(wait) = over_rate {
+            let _unused = queue.0.cond.wait_timeout(m, wait);
+            continue;
+        }
+        if let Some((task,postdo)) = m.pop_front() {
+            drop(m);
+            queue.0.metrics.popped(1);
+            if let Some(Kind::Exit) = run_popped(task, postdo, &queue) {
+                warn!("received an exit message and prepare to exit.");
+                break;
+            }
+        } else {
+            let _unused = queue.0.cond.wait(m);
+        }
+    }
+    info!("current thread exited normally.");
+}
+
+/// Spawns a worker serving several queues by smooth weighted round robin.
+///
+/// With the weights `[(q1,3),(q2,1)]`, the worker takes 3 tasks from `q1` per task from `q2`
+/// while both have tasks, a queue never starves as long as its weight is not zero,
+/// and an empty or over-rate queue gives its turns to the others.
+pub fn spawn_weighted_thread(queues:&[(&Queue,u32)])-> Jhandle {
+    let quit_flag = Arc::<AtomicBool>::new(AtomicBool::new(false));
+    let quit = quit_flag.clone();
+    let signal = Arc::new(Signal::default());
+    let mut queues: Vec<(Queue,i64,i64)> = queues.iter()
+        .map(|(queue,weight)|((*queue).clone(),*weight as i64,0))
+        .collect();
+    for (queue,_,_) in queues.iter() {
+        queue.0.watchers.lock().unwrap().push(signal.clone());
+    }
     let handle = thread::spawn(move||{
-        warn!("starts ok.");
-        loop {
+        let _running = Running::start(queues.iter().find_map(|(queue,_,_)|queue.hooks()));
+        let _worker = Worker::start(queues.iter().find_map(|(queue,_,_)|queue.0.shared.get()).map(|(_,shared)|shared.metrics.workers()));
+        warn!("starts ok, serving {} queues by weight.", queues.len());
+        'run: loop {
             if quit.load(Ordering::Relaxed) {
                 warn!("Quit flag detected and prepare to exit.");
                 break;
             }
-            
-            let mut m = queue.0.lock().unwrap();
-            if let Some((task,postdo)) = m.pop_front() {
-                drop(m);
-                debug!("task#{:?} is scheduled to run.",task.id());
-                let kind = task.kind();
-                let r = task.run();
-                if let Some(r) = r {
-                    postdo(r);
+
+            // only the queues with tasks earn credits in the round, an empty one starts again from zero,
+            // so a queue can not bank credits while idle, nor run into debt while alone.
+            let mut ready = Vec::with_capacity(queues.len());
+            for (i,(queue,weight,credit)) in queues.iter_mut().enumerate() {
+                if queue.is_empty() {
+                    *credit = 0;
+                } else {
+                    *credit += *weight;
+                    ready.push(i);
                 }
-                if let Kind::Exit = kind {
-                    warn!("received an exit message and prepare to exit.");
-                    break;
+            }
+            let total: i64 = ready.iter().map(|i|queues[*i].1).sum();
+            // the queues in the order of their credits, the first one deserves the turn
+            ready.sort_by_key(|i|std::cmp::Reverse(queues[*i].2));
+
+            let mut wait = None::<Duration>;
+            for i in ready {
+                match queues[i].0.try_pop() {
+                    Ok((task,postdo)) => {
+                        queues[i].2 -= total;
+                        let queue = queues[i].0.clone();
+                        if let Some(Kind::Exit) = run_popped(task, postdo, &queue) {
+                            warn!("received an exit message and prepare to exit.");
+                            break 'run;
+                        }
+                        continue 'run;
+                    }
+                    Err(over_rate) => {
+                        // the credit is not earned for a turn not taken, e.g. over its rate
+                        queues[i].2 -= queues[i].1;
+                        if let Some(over_rate) = over_rate {
+                            wait = Some(wait.map_or(over_rate, |wait|wait.min(over_rate)));
+                        }
+                    }
                 }
-            } else {
-                let _unused = queue.1.wait(m);
             }
+            signal.wait(wait);
+        }
+        for (queue,_,_) in queues.iter() {
+            queue.0.watchers.lock().unwrap().retain(|watcher|!Arc::ptr_eq(watcher, &signal));
         }
         info!("current thread exited normally.");
     });
     Jhandle(handle,quit_flag)
 }
 
+/// A queue known by the pool without keeping it alive.
+pub(crate) struct WeakQueue(Weak<QueueInner>);
+
+impl WeakQueue {
+    /// the count of the tasks ready in the queue, 0 if the queue is gone
+    pub(crate) fn depth(&self)->usize {
+        self.0.upgrade().map_or(0, |inner|inner.tasks.lock().unwrap().len())
+    }
+}
+
+/// Wakes a worker serving several queues, whenever a task is added into any of them.
+#[derive(Default)]
+pub(crate) struct Signal {
+    pending: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Signal {
+    fn notify(&self) {
+        *self.pending.lock().unwrap() = true;
+        self.cond.notify_all();
+    }
+
+    /// waits until notified, or the timeout if any, a notification coming earlier is not missed.
+    fn wait(&self, timeout:Option<Duration>) {
+        let mut pending = self.pending.lock().unwrap();
+        if !*pending {
+            pending = match timeout {
+                Some(timeout) => self.cond.wait_timeout(pending, timeout).unwrap().0,
+                None => self.cond.wait(pending).unwrap(),
+            };
+        }
+        *pending = false;
+    }
+}
+
+/// the task is popped after its deadline and dropped, reported to its handler if any.
+fn miss_deadline(mut task:Box<dyn Task+Send>, deadline:Instant, late:Duration, bound:Option<&(usize,Shared)>, queue:&Queue) {
+    let missed = DeadlineMissed { taskid: task.id(), deadline, late };
+    warn!(task_id=missed.taskid; "task#{:?} missed its deadline by {late:?} and is dropped.", missed.taskid);
+    if let DeadlineMiss::Report(ca) = task.attr_mut().on_deadline_miss {
+        if let Some((qid,shared)) = bound {
+            let _ = when_ci_comed(&ca, (&missed,&missed.taskid), shared.c1map.clone(), (*qid,queue.clone()));
+        } else {
+            error!(task_id=missed.taskid; "the queue of task#{:?} is not inserted into a pool, the miss cannot be reported.", missed.taskid);
+        }
+    }
+}
+
+/// admits and runs a task popped from the queue,
+/// returns the kind of the task, or None if the task is put aside rather than run.
+pub(crate) fn run_popped(mut task:Box<dyn Task+Send>, postdo:Box<PostDo>, queue:&Queue)->Option<Kind> {
+    let bound = queue.0.shared.get();
+    if let Some(deadline) = task.attr_mut().deadline {
+        let now = Instant::now();
+        if now > deadline {
+            queue.0.metrics.cancelled(task.attr_mut().tag);
+            let taskid = task.id();
+            let kind = task.kind();
+            queue.emit(|qid|PoolEvent::Cancelled { taskid, qid });
+            miss_deadline(task, deadline, now - deadline, bound, queue);
+            // the body of an exit task is dropped, but the worker exits still
+            if kind != Kind::Exit {
+                return None;
+            }
+            queue.emit(|qid|PoolEvent::ExitTaskRun { taskid, qid });
+            return Some(kind);
+        }
+    }
+    let shared = bound.map(|(_,shared)|shared);
+    let (mut task,postdo) = match (shared,task.attr_mut().tag) {
+        (Some(shared),Some(tag)) => shared.rates.admit(tag, task, postdo, queue)?,
+        _ => (task,postdo),
+    };
+    let (mut task,postdo,_permit) = match shared {
+        Some(shared) if !task.attr_mut().requires.is_empty() => {
+            let (task,postdo,permit) = shared.limits.acquire(task, postdo, queue)?;
+            (task,postdo,Some(permit))
+        }
+        _ => (task,postdo,None),
+    };
+    let kind = task.kind();
+    let taskid = task.id();
+    // the logs of the run and of the deliveries from its result are in the span of the task
+    #[cfg(feature = "tracing")]
+    let _span = ::tracing::info_span!(target: crate::log::NAME, "task",
+        task_id = crate::log::Field::field(&taskid),
+        qid = bound.map(|(qid,_)|*qid as u64),
+        kind = ?kind,
+    ).entered();
+    debug!(task_id=taskid; "task#{taskid:?} is scheduled to run.");
+    let tag = task.attr_mut().tag;
+    let info = bound.map(|(qid,shared)|(TaskInfo { taskid, kind, qid: *qid },&shared.hooks));
+    if let Some((info,hooks)) = &info {
+        hooks.before(info);
+    }
+    queue.emit(|qid|PoolEvent::Started { taskid, qid, kind });
+    let _running = shared.map(|shared|shared.activity.run());
+    queue.0.metrics.dequeued(task.attr_mut());
+    let start = Instant::now();
+    // an awaited task keeps its watch from the first poll
+    let mut watch = match task.attr_mut().watch.take() {
+        Some(watch) => Some(watch),
+        None => task.attr_mut().timeout.map(|timeout|Watch::start(taskid, timeout)),
+    };
+    let _entered = watch.as_ref().map(Watch::enter);
+    let token = watch.as_ref().map(Watch::token);
+    let done = if task.attr_mut().retry.is_some() {
+        run_retry(task, postdo, queue)
+    } else if task.attr_mut().awaited {
+        task.attr_mut().watch = watch.take();
+        future::poll_popped(task, postdo, queue)
+    } else {
+        match catch_unwind(AssertUnwindSafe(||task.run())) {
+            Ok(r) => Some((r, postdo)),
+            Err(panic) => {
+                queue.0.metrics.panicked(tag);
+                queue.emit(|qid|PoolEvent::Panicked { taskid, qid });
+                resume_unwind(panic)
+            }
+        }
+    };
+    queue.0.metrics.ran(tag, start.elapsed());
+    let timed_out = token.is_some_and(|token|token.is_cancelled());
+    drop(watch);
+    let done_ok = done.is_some();
+    match (timed_out,&done) {
+        (true,_) => {
+            queue.0.metrics.cancelled(tag);
+            queue.emit(|qid|PoolEvent::Cancelled { taskid, qid });
+        }
+        (false,Some(_)) => queue.0.metrics.completed(tag),
+        (false,None) => {}
+    }
+    if let Some((Some(r),postdo)) = done {
+        if timed_out {
+            warn!(task_id=taskid; "task#{taskid:?} timed out, its result is dropped.");
+        } else {
+            postdo(r);
+        }
+    }
+    let elapsed = start.elapsed();
+    if !timed_out && done_ok {
+        if let Some(shared) = shared {
+            shared.c1map.journal_completed(taskid);
+        }
+        queue.emit(|qid|PoolEvent::Completed { taskid, qid, elapsed });
+    }
+    if kind == Kind::Exit && done_ok {
+        queue.emit(|qid|PoolEvent::ExitTaskRun { taskid, qid });
+    }
+    Worker::busy(elapsed);
+    trace::ran(taskid, bound.map_or(0, |(qid,_)|*qid), kind, start, elapsed);
+    if let Some((info,hooks)) = &info {
+        hooks.after(info, elapsed);
+    }
+    // the task runs again later, e.g. retried, it has not finished yet even if an exit task
+    if !done_ok {
+        return None;
+    }
+    Some(kind)
+}
+
+/// runs a task built with `retry()`, a failed run is added into the queue again after the backoff.
+/// returns the final result to pass on, or None if the task will run again.
+fn run_retry(mut task:Box<dyn Task+Send>, postdo:Box<PostDo>, queue:&Queue)->future::Polled {
+    let r = catch_unwind(AssertUnwindSafe(||task.run_mut()));
+    let _taskid = task.id();
+    let tag = task.attr_mut().tag;
+    let Some(retry) = task.attr_mut().retry.as_mut() else {
+        unreachable!("the retry has checked before run_retry()!");
+    };
+    let failed = match &r {
+        Err(_) => true,
+        Ok(r) => match (&retry.is_failure, r) {
+            (Some(is_failure), Some(r)) => is_failure(r.as_ref()),
+            _ => false,
+        },
+    };
+    if failed && retry.failures + 1 < retry.policy.max_attempts() {
+        retry.failures += 1;
+        let delay = retry.policy.backoff(retry.failures);
+        warn!(task_id=_taskid; "task#{_taskid:?} failed at run#{} and will run again in {delay:?}.", retry.failures);
+        queue.add_later(delay, task, postdo, false);
+        return None;
+    }
+    if failed {
+        error!(task_id=_taskid; "task#{_taskid:?} failed at the final run#{}.", retry.failures + 1);
+    }
+    match r {
+        Ok(r) => Some((r,postdo)),
+        Err(panic) => {
+            queue.0.metrics.panicked(tag);
+            queue.emit(|qid|PoolEvent::Panicked { taskid: _taskid, qid });
+            resume_unwind(panic)
+        }
+    }
+}
+
+/// A conditional task waiting in `C1map` for its conds.
+pub(crate) struct Waiting {
+    task: Box<dyn Task+Send>,
+    postdo: Box<PostDo>,
+    /// the optional conds neither delivered nor timed out yet
+    optional: Vec<Pi>,
+    /// the queue of the submitter, used when the task is released by a timer rather than by a producer
+    home: (usize,Queue),
+    /// tells the timers apart from those of a former task with the same id
+    serial: u64,
+    submitted_at: Instant,
+}
+
+impl Waiting {
+    fn is_ready(&mut self)->bool {
+        self.optional.is_empty()
+            && self.task.as_param_mut().is_some_and(|param|param.is_full())
+    }
+}
+
+static WAITING_SERIAL: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Clone)]
-pub(crate) struct C1map(Arc<(Mutex<HashMap<NonZeroUsize,(Box<dyn Task+Send>,Box<PostDo>)>>,Condvar)>);
+pub(crate) struct C1map(Arc<(Mutex<HashMap<NonZeroUsize,Waiting>>,Condvar,RwLock<Option<Journal>>)>);
 
 impl C1map {
     pub(crate) fn new()->Self {
         Self(
-            Arc::new((Mutex::new(HashMap::new()),Condvar::new()))
+            Arc::new((Mutex::new(HashMap::new()),Condvar::new(),RwLock::new(None)))
         )
     }
+    /// appends the deliveries and the completions to the journal from now on, see `Pool::set_journal()`.
+    pub(crate) fn set_journal(&self, journal:Journal) {
+        *self.0.2.write().unwrap() = Some(journal);
+    }
+    /// records the completion of the task in the journal if any.
+    pub(crate) fn journal_completed(&self, taskid:TaskId) {
+        if let Some(journal) = &*self.0.2.read().unwrap() {
+            journal.completed(taskid);
+        }
+    }
+    /// the count of the tasks waiting for their conds
+    #[allow(dead_code)]
+    pub(crate) fn len(&self)->usize {
+        self.0.0.lock().unwrap().len()
+    }
     pub(crate) fn check(&self, tid:TaskId)->Option<TaskId> {
         let TaskId(Some(ref taskid)) = tid else {
             return None;
@@ -117,10 +636,13 @@ impl C1map {
             None
         }
     }
-    pub(crate) fn try_insert<T>(&self,task: T,postdo:Box<PostDo>,taskid:NonZeroUsize)->Option<NonZeroUsize>
+    pub(crate) fn try_insert<T>(&self,task: T,postdo:Box<PostDo>,taskid:NonZeroUsize,home:(usize,Queue))->Option<NonZeroUsize>
     where T: Task + Send + 'static
     {
-        let task: Box::<dyn Task + Send + 'static> = Box::new(task);
+        let mut task: Box::<dyn Task + Send + 'static> = Box::new(task);
+        let optional = std::mem::take(&mut task.attr_mut().optional);
+        let cond_timeout = task.attr_mut().cond_timeout;
+        let serial = WAITING_SERIAL.fetch_add(1, Ordering::Relaxed);
         let mut lock = self.0.0.lock().unwrap();
         use std::collections::hash_map::Entry::{Occupied,Vacant};
         match lock.entry(taskid) {
@@ -128,70 +650,331 @@ impl C1map {
                 => None,
             Vacant(vacant_entry)
                 => {
-                vacant_entry.insert((task,postdo));
+                // the events are emitted after the lock is released, a listener may deliver or submit again
+                let submitted = |home:&Queue|home.emit(|qid|PoolEvent::Submitted { taskid: TaskId(Some(taskid)), qid });
+                let mut waiting = Waiting {
+                    task, postdo,
+                    optional: optional.iter().map(|(pi,_)|*pi).collect(),
+                    home,
+                    serial,
+                    submitted_at: Instant::now(),
+                };
+                // all the conds are filled with defaults, nothing to wait for
+                if waiting.is_ready() {
+                    drop(lock);
+                    submitted(&waiting.home.1);
+                    debug!(task_id=taskid, qid=waiting.home.0; "cond task#{taskid:?} has all conditions filled by defaults and scheduled to Q#{}", waiting.home.0);
+                    self.release(waiting.task, waiting.postdo, &waiting.home.1);
+                    return Some(taskid);
+                }
+                let waiting = vacant_entry.insert(waiting);
+                let home = waiting.home.1.clone();
+                drop(lock);
+                submitted(&home);
+                home.emit(|_|PoolEvent::WaitingForConds { taskid: TaskId(Some(taskid)) });
+                for (pi,timeout) in optional {
+                    let c1map = self.clone();
+                    timer::schedule(timeout, move||c1map.expire_optional(taskid, serial, pi));
+                }
+                if let Some(timeout) = cond_timeout {
+                    let c1map = self.clone();
+                    timer::schedule(timeout, move||c1map.expire_task(taskid, serial));
+                }
                 Some(taskid)
             },
         }
     }
-    fn remove(&self,id:&NonZeroUsize)->Option<(Box<dyn Task+Send>,Box<PostDo>)> {
+    /// schedules the task released from waiting, with the tightest deadline downstream if inherited.
+    fn release(&self, mut task:Box<dyn Task+Send>, postdo:Box<PostDo>, q:&Queue) {
+        if task.attr_mut().inherit_deadline {
+            let downstream = self.downstream_deadline(task.attr_mut().next);
+            let attr = task.attr_mut();
+            if downstream.is_some_and(|d|attr.deadline.is_none_or(|own|d < own)) {
+                debug!(task_id=task.id(); "task#{:?} inherits the deadline of its downstream chain.", task.id());
+                task.attr_mut().deadline = downstream;
+            }
+        }
+        let taskid = task.id();
+        trace::released(taskid, q.0.shared.get().map_or(0, |(qid,_)|*qid));
+        q.emit(|qid|PoolEvent::Released { taskid, qid });
+        q.add_boxtask(task, postdo);
+    }
+
+    /// the waiting tasks with the state of their conds, in the order of their ids
+    pub(crate) fn pending(&self)->Vec<PendingTask> {
+        let mut lock = self.0.0.lock().unwrap();
+        let mut pending: Vec<PendingTask> = lock.iter_mut()
+            .map(|(taskid,waiting)|{
+                let kind = waiting.task.kind();
+                let conds = match waiting.task.as_param_mut() {
+                    Some(param) => (0..param.arity())
+                        .map(|i|CondState {
+                            pi: Pi(i as u8),
+                            type_name: param.typename(i),
+                            filled: param.is_set(i),
+                            optional: waiting.optional.contains(&Pi(i as u8)),
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                PendingTask {
+                    taskid: TaskId(Some(*taskid)),
+                    kind,
+                    qid: waiting.home.0,
+                    arity: conds.len(),
+                    conds,
+                    submitted_at: waiting.submitted_at,
+                }
+            })
+            .collect();
+        pending.sort_by_key(|task|task.taskid.0);
+        pending
+    }
+
+    /// passes each waiting task with the id of its queue to `f`, in the order of their ids.
+    pub(crate) fn try_for_each<E>(&self, mut f:impl FnMut(usize,&mut (dyn Task+Send))->Result<(),E>)->Result<(),E> {
+        let mut lock = self.0.0.lock().unwrap();
+        let mut waiting: Vec<_> = lock.iter_mut().collect();
+        waiting.sort_by_key(|(taskid,_)|**taskid);
+        waiting.into_iter().try_for_each(|(_,waiting)|f(waiting.home.0, waiting.task.as_mut()))
+    }
+
+    /// the waiting tasks with the conds they miss, but those going to be resolved by a timer,
+    /// i.e. with optional conds not expired yet or with a `cond_timeout()`.
+    pub(crate) fn stuck(&self)->Vec<StuckTask> {
+        let mut lock = self.0.0.lock().unwrap();
+        // the known producers of each cond, the waiting tasks built with `to()` it
+        let mut producers: HashMap<(NonZeroUsize,u8),Vec<TaskId>> = HashMap::new();
+        for (taskid,waiting) in lock.iter_mut() {
+            if let Some(ca) = waiting.task.attr_mut().next
+                && let TaskId(Some(target)) = ca.taskid()
+            {
+                producers.entry((target,ca.pi().0)).or_default().push(TaskId(Some(*taskid)));
+            }
+        }
+        let mut stuck = Vec::new();
+        for (taskid,waiting) in lock.iter_mut() {
+            if !waiting.optional.is_empty() || waiting.task.attr_mut().cond_timeout.is_some() {
+                continue;
+            }
+            let Some(param) = waiting.task.as_param_mut() else {
+                continue;
+            };
+            let missing = (0..param.arity())
+                .filter(|i|!param.is_set(*i))
+                .map(|i|MissingCond {
+                    pi: Pi(i as u8),
+                    type_name: param.typename(i),
+                    producers: producers.remove(&(*taskid,i as u8)).unwrap_or_default(),
+                })
+                .collect();
+            stuck.push(StuckTask { taskid: TaskId(Some(*taskid)), qid: waiting.home.0, missing });
+        }
+        stuck
+    }
+
+    /// the tightest deadline of the waiting tasks along the chain from `next`.
+    fn downstream_deadline(&self, next:Option<CondAddr>)->Option<Instant> {
+        let mut next = next.map(|ca|ca.taskid());
+        let mut lock = self.0.0.lock().unwrap();
+        // a chain never visits more tasks than waiting, unless it is a cycle
+        let mut hops = lock.len();
+        let mut tightest = None;
+        while let Some(TaskId(Some(taskid))) = next {
+            let Some(waiting) = lock.get_mut(&taskid) else {
+                break;
+            };
+            let attr = waiting.task.attr_mut();
+            tightest = tightest.into_iter().chain(attr.deadline).min();
+            next = attr.next.map(|ca|ca.taskid());
+            hops = match hops.checked_sub(1) {
+                Some(hops) => hops,
+                None => break,
+            };
+        }
+        tightest
+    }
+
+    fn remove(&self,id:&NonZeroUsize)->Option<Waiting> {
         let mut lock = self.0.0.lock().unwrap();
         lock.remove(id)
     }
 
-    // Some(true): full
-    // Some(false): not full
-    // None: error
-    fn update_ci<T:'static+Debug>(&self,target_ca:&CondAddr,(v,v_from):(&T,&TaskId))->Option<bool> {
-        let TaskId(Some(ref target_taskid)) = target_ca.taskid() else {
-            error!("task#{:?} is ZERO, not avaiable!", target_ca.taskid());
-            return None;
+    /// the optional cond#`pi` has not been delivered in time, the task goes on with its default.
+    fn expire_optional(&self, taskid:NonZeroUsize, serial:u64, pi:Pi) {
+        let mut lock = self.0.0.lock().unwrap();
+        let Some(waiting) = lock.get_mut(&taskid) else {
+            // has been released already
+            return;
         };
+        if waiting.serial != serial || !waiting.optional.contains(&pi) {
+            return;
+        }
+        waiting.optional.retain(|p|*p != pi);
+        debug!(task_id=taskid, pi=pi; "task#{taskid:?}.cond#{pi:?} timed out, the default is used.");
+        if !waiting.is_ready() {
+            return;
+        }
+        let Waiting {task, postdo, home:(_qid,q), ..} = lock.remove(&taskid).unwrap();
+        drop(lock);
+        debug!(task_id=taskid, qid=_qid; "cond task#{taskid:?} has all conditions been satified and scheduled to Q#{_qid}");
+        self.release(task, postdo, &q);
+    }
+
+    /// the conds of the task have not been all delivered in time, the task is given up.
+    fn expire_task(&self, taskid:NonZeroUsize, serial:u64) {
         let mut lock = self.0.0.lock().unwrap();
-        let Some((target_task,_target_postdo)) = lock.get_mut(target_taskid) else {
-            error!("task#{:?} was not found, the cond#{:?} could not be updated", target_ca.taskid(), target_ca.pi());
+        if lock.get(&taskid).is_none_or(|waiting|waiting.serial != serial) {
+            // has been released already
+            return;
+        }
+        let mut given_up = (taskid,lock.remove(&taskid).unwrap());
+        drop(lock);
+        // the task waiting for the result of a task given up would wait forever, it is given up as well
+        loop {
+            let (taskid,Waiting {mut task, home, ..}) = given_up;
+            let missing = task.as_param_mut()
+                .map(|param|
+                    (0..param.arity())
+                    .filter(|i|!param.is_set(*i))
+                    .map(|i|Pi(i as u8))
+                    .collect()
+                ).unwrap_or_default();
+            let timeout = CondTimeout { taskid: TaskId(Some(taskid)), missing };
+            warn!(task_id=taskid; "cond task#{taskid:?} timed out waiting for cond#{:?} and is given up.", timeout.missing);
+            if let Some(ca) = task.attr_mut().on_cond_timeout.take() {
+                let _ = when_ci_comed(&ca, (&timeout,&timeout.taskid), self.clone(), home);
+            }
+            let Some(next) = task.attr_mut().next.and_then(|ca|self.give_up_downstream(&ca, taskid)) else {
+                break;
+            };
+            given_up = next;
+        }
+    }
+
+    /// the task waiting for the cond `ca` from the task `from` given up, taken out to be given up as well,
+    /// unless the cond is filled already, or optional and then the task goes on with its default.
+    fn give_up_downstream(&self, ca:&CondAddr, _from:NonZeroUsize)->Option<(NonZeroUsize,Waiting)> {
+        let TaskId(Some(taskid)) = ca.taskid() else {
             return None;
         };
-        let Some(param) = target_task.as_param_mut() else {
-            error!("task#{:?} failed to acquire cond#{:?}, update skipped.", target_ca.taskid(), target_ca.pi());
+        let pi = ca.pi();
+        let mut lock = self.0.0.lock().unwrap();
+        let waiting = lock.get_mut(&taskid)?;
+        if waiting.optional.contains(&pi) {
+            let serial = waiting.serial;
+            drop(lock);
+            self.expire_optional(taskid, serial, pi);
             return None;
+        }
+        if waiting.task.as_param_mut().is_none_or(|param|param.is_set(pi.0 as usize)) {
+            return None;
+        }
+        warn!(task_id=taskid, from_task=_from, pi=pi; "task#{_from:?} delivering to task#{taskid:?}.cond#{pi:?} is given up.");
+        lock.remove(&taskid).map(|waiting|(taskid,waiting))
+    }
+
+    // Ok(Some): full, the task is taken out to release
+    // Ok(None): not full
+    fn update_ci<T:'static+Debug>(&self,target_ca:&CondAddr,(v,v_from):(&T,&TaskId))->Result<Option<Waiting>,DeliveryError> {
+        let TaskId(Some(ref target_taskid)) = target_ca.taskid() else {
+            error!("task#{:?} is ZERO, not avaiable!", target_ca.taskid());
+            return Err(DeliveryError::ZeroTaskId(*target_ca));
+        };
+        let mut lock = self.0.0.lock().unwrap();
+        let Some(waiting) = lock.get_mut(target_taskid) else {
+            error!(task_id=target_ca.taskid(), pi=target_ca.pi(); "task#{:?} was not found, the cond#{:?} could not be updated", target_ca.taskid(), target_ca.pi());
+            return Err(DeliveryError::NotFound(*target_ca));
+        };
+        let name = waiting.task.attr_mut().name;
+        let Some(param) = waiting.task.as_param_mut() else {
+            error!(task_id=target_ca.taskid(), pi=target_ca.pi(); "task#{:?} failed to acquire cond#{:?}, update skipped.", target_ca.taskid(), target_ca.pi());
+            return Err(DeliveryError::NoParams(*target_ca));
         };
         if !param.set(target_ca.pi().0 as usize, v) {
             let _target_taskid = target_ca.taskid();
             let _target_i = target_ca.pi();
-            let _target_type_name = param.typename(_target_i.0 as usize);
-            let _data_type_name  = type_name::<T>();
-            error!("target task#{_target_taskid:?}.cond#{_target_i:?} has type <{_target_type_name}> not identical to <{_data_type_name}>, \
+            let target_type_name = param.typename(_target_i.0 as usize);
+            let data_type_name  = type_name::<T>();
+            error!(task_id=_target_taskid, from_task=*v_from, pi=_target_i, type_name=data_type_name;
+                "target task#{_target_taskid:?}.cond#{_target_i:?} has type <{target_type_name}> not identical to <{data_type_name}>, \
                     cannot be updated with from task#{v_from:?}.{{{v:?}}}.");
-            return None;
+            return Err(DeliveryError::TypeMismatch { ca: *target_ca, expected: target_type_name, actual: data_type_name });
         }
         if cfg!(feature="log-trace") {
-            trace!("target task#{:?} received from task#{v_from:?}.cond#{:?}={{{v:?}}}", target_ca.taskid(),target_ca.pi());
+            trace!(task_id=target_ca.taskid(), from_task=*v_from, pi=target_ca.pi(); "target task#{:?} received from task#{v_from:?}.cond#{:?}={{{v:?}}}", target_ca.taskid(),target_ca.pi());
         } else {
-            debug!("target task#{:?} received from task#{v_from:?}.cond#{:?}", target_ca.taskid(),target_ca.pi());
+            debug!(task_id=target_ca.taskid(), from_task=*v_from, pi=target_ca.pi(); "target task#{:?} received from task#{v_from:?}.cond#{:?}", target_ca.taskid(),target_ca.pi());
+        }
+        // the record is encoded under the lock, but written after it is released
+        let journal = self.0.2.read().unwrap();
+        let record = journal.as_ref().and_then(|journal|journal.delivered(*v_from, *target_ca, name, param));
+        waiting.optional.retain(|pi|*pi != target_ca.pi());
+        // a full task is taken under the same lock, so a timer can not give it up once delivered
+        let released = match waiting.is_ready() {
+            true => lock.remove(target_taskid),
+            false => None,
+        };
+        drop(lock);
+        if let (Some(journal),Some(record)) = (&*journal,record) {
+            journal.append(record);
+        }
+        Ok(released)
+    }
+
+    /// fills the cond of the waiting task by `fill`, given the name of the task, bypassing the journal,
+    /// and releases the task to its queue once ready. None if the task is not waiting or `fill` fails.
+    pub(crate) fn replay_ci(&self, ca:&CondAddr, fill:impl FnOnce(&str,&mut dyn CallParam)->bool)->Option<bool> {
+        let TaskId(Some(taskid)) = ca.taskid() else {
+            return None;
+        };
+        let mut lock = self.0.0.lock().unwrap();
+        let waiting = lock.get_mut(&taskid)?;
+        let name = waiting.task.attr_mut().name?;
+        if !fill(name, waiting.task.as_param_mut()?) {
+            return None;
         }
-        Some(param.is_full())
+        waiting.optional.retain(|pi|*pi != ca.pi());
+        if !waiting.is_ready() {
+            return Some(false);
+        }
+        let Waiting {task, postdo, home:(_qid,q), ..} = lock.remove(&taskid).unwrap();
+        drop(lock);
+        debug!(task_id=taskid, qid=_qid; "cond task#{taskid:?} has all conditions replayed and scheduled to Q#{_qid}");
+        self.release(task, postdo, &q);
+        Some(true)
+    }
+
+    /// removes the waiting task, e.g. completed before a crash, see `Pool::replay()`.
+    pub(crate) fn discard(&self, taskid:TaskId)->bool {
+        let TaskId(Some(taskid)) = taskid else {
+            return false;
+        };
+        self.remove(&taskid).is_some()
     }
 }
 
 // tid and qid just used for log
+// Ok(true): the target is released
+// Ok(false): the target is still waiting
+// Err: the value is not delivered, and passed to the dead-letter handlers of the pool
 #[allow(unused_variables)]
-pub(crate) fn when_ci_comed<T:'static+Debug>(target_ca:&CondAddr, (v,v_from):(&T,&TaskId), c1map:C1map, (qid,q):(usize,Queue))->bool {
-    let Some(true) = c1map.update_ci(target_ca,(v,v_from)) else {
-        // the log has been processed in update_ci
-        return false;
-    };
-
-    let TaskId(Some(ref target_taskid)) = target_ca.taskid() else {
-        unreachable!("the taskid has checked in update_ci()!");
-        return false;
-    };
-    let Some((target_task,postdo)) = c1map.remove(target_taskid) else {
-        error!("cond task#{:?} does not find.",target_ca.taskid());
-        return  false;
-    };
-    debug!("cond task#{:?} has all conditions been satified and scheduled to Q#{qid}", target_ca.taskid());
-    q.add_boxtask(target_task,postdo);
-    true
+pub(crate) fn when_ci_comed<T:'static+Debug>(target_ca:&CondAddr, (v,v_from):(&T,&TaskId), c1map:C1map, (qid,q):(usize,Queue))->Result<bool,DeliveryError> {
+    let full = c1map.update_ci(target_ca,(v,v_from)).map(|full|{
+        trace::delivered(*v_from, *target_ca);
+        q.emit(|_|PoolEvent::CondDelivered { taskid: target_ca.taskid(), pi: target_ca.pi(), from: *v_from });
+        let Some(Waiting {task:target_task, postdo, ..}) = full else {
+            return false;
+        };
+        debug!(task_id=target_ca.taskid(), qid=qid; "cond task#{:?} has all conditions been satified and scheduled to Q#{qid}", target_ca.taskid());
+        c1map.release(target_task, postdo, &q);
+        true
+    });
+    // the log has been processed in update_ci
+    if let (Err(error),Some(hooks)) = (&full,q.hooks()) {
+        hooks.dead_letter(||DeadLetter { from: *v_from, error: error.clone(), value: format!("{v:?}") });
+    }
+    full
 }
 
 #[allow(dead_code)]
@@ -210,7 +993,7 @@ impl WhenTupleComed for () {
 
 impl<T:'static+Debug> WhenTupleComed for ((T,CondAddr),) {
     fn foreach(&self, id_from:&TaskId, c1map:C1map, q:(usize,Queue)) {
-        when_ci_comed(&self.0.1, (&self.0.0,id_from), c1map, q);
+        let _ = when_ci_comed(&self.0.1, (&self.0.0,id_from), c1map, q);
     }
 }
 
@@ -219,7 +1002,7 @@ macro_rules! when_tuple_comed_impl {
         impl< $($T:'static+Debug),+ > WhenTupleComed for ($(($T, CondAddr)),+) {
             fn foreach(&self, id_from:&TaskId, c1map: C1map, q: (usize,Queue)) {
                 $(
-                    when_ci_comed(&self.$i.1, (&self.$i.0,id_from), c1map.clone(), q.clone());
+                    let _ = when_ci_comed(&self.$i.1, (&self.$i.0,id_from), c1map.clone(), q.clone());
                 )+
             }
         }
@@ -248,3 +1031,368 @@ when_tuple_comed_impl!(0 T1, 1 T2, 2 T3, 3 T4, 4 T5);
 when_tuple_comed_impl!(0 T1, 1 T2, 2 T3, 3 T4, 4 T5, 5 T6);
 when_tuple_comed_impl!(0 T1, 1 T2, 2 T3, 3 T4, 4 T5, 5 T6, 6 T7);
 when_tuple_comed_impl!(0 T1, 1 T2, 2 T3, 3 T4, 4 T5, 5 T6, 6 T7, 7 T8);
+
+#[cfg(test)]
+mod test_cond {
+    use std::time::Duration;
+    use super::*;
+    use crate::{TaskBuildNew, TaskSubmitter};
+
+    fn submitter()->TaskSubmitter {
+        TaskSubmitter { qid: 1, queue: Queue::new(), c1map: C1map::new() }
+    }
+
+    fn run_next(queue:&Queue)->i32 {
+        let (task,_postdo) = queue.pop().unwrap();
+        *task.run().unwrap().downcast::<i32>().unwrap()
+    }
+
+    #[test]
+    fn test_default_cond() {
+        let submitter = submitter();
+        // all conds are defaulted, scheduled at once
+        let task = (|a:i32,b:i32|a+b).into_task()
+            .default_cond(Pi::PI0, 1)
+            .default_cond(Pi::PI1, 2);
+        submitter.submit(task).unwrap();
+        assert_eq!(run_next(&submitter.queue), 3);
+
+        // the delivered value replaces the default
+        let task = (|a:i32,b:i32|a+b).into_task().default_cond(Pi::PI1, 2);
+        let id = submitter.submit(task).unwrap();
+        assert_eq!(submitter.queue.len(), 0);
+        let to = (id,Pi::PI0).into();
+        assert_eq!(when_ci_comed(&to, (&5,&TaskId::NONE), submitter.c1map.clone(), (1,submitter.queue.clone())), Ok(true));
+        assert_eq!(run_next(&submitter.queue), 7);
+    }
+
+    #[test]
+    fn test_optional_cond() {
+        let submitter = submitter();
+        let c1q = (1,submitter.queue.clone());
+
+        // delivered in time
+        let task = (|a:i32,b:Option<i32>|a+b.unwrap_or(0)).into_task()
+            .optional_cond(Pi::PI1, None::<i32>, Duration::from_secs(60));
+        let id = submitter.submit(task).unwrap();
+        assert_eq!(when_ci_comed(&(id,Pi::PI0).into(), (&1,&TaskId::NONE), submitter.c1map.clone(), c1q.clone()), Ok(false));
+        assert_eq!(when_ci_comed(&(id,Pi::PI1).into(), (&Some(2),&TaskId::NONE), submitter.c1map.clone(), c1q.clone()), Ok(true));
+        assert_eq!(run_next(&submitter.queue), 3);
+
+        // timed out
+        let task = (|a:i32,b:Option<i32>|a+b.unwrap_or(0)).into_task()
+            .optional_cond(Pi::PI1, None::<i32>, Duration::from_millis(10));
+        let id = submitter.submit(task).unwrap();
+        assert_eq!(when_ci_comed(&(id,Pi::PI0).into(), (&1,&TaskId::NONE), submitter.c1map.clone(), c1q.clone()), Ok(false));
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(run_next(&submitter.queue), 1);
+    }
+
+    #[test]
+    fn test_cond_timeout() {
+        let submitter = submitter();
+        let c1q = (1,submitter.queue.clone());
+        let handler = submitter.submit((|t:CondTimeout|t).into_task()).unwrap();
+        let task = (|a:i32,b:i32|a+b).into_task()
+            .cond_timeout(Duration::from_millis(10))
+            .on_cond_timeout((handler,Pi::PI0).into());
+        let id = submitter.submit(task).unwrap();
+        assert_eq!(when_ci_comed(&(id,Pi::PI0).into(), (&1,&TaskId::NONE), submitter.c1map.clone(), c1q.clone()), Ok(false));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(submitter.c1map.check(id).is_none());
+        let (task,_postdo) = submitter.queue.pop().unwrap();
+        let timeout = task.run().unwrap().downcast::<CondTimeout>().unwrap();
+        assert_eq!(*timeout, CondTimeout { taskid: id, missing: vec![Pi::PI1] });
+    }
+
+    #[test]
+    fn test_cond_timeout_cascades() {
+        let submitter = submitter();
+        let handler = submitter.submit((|t:CondTimeout|t).into_task()).unwrap();
+        // sink <- middle <- source, only the source times out by itself
+        let sink = submitter.submit((|a:i32,b:i32|a+b).into_task()
+            .on_cond_timeout((handler,Pi::PI0).into())).unwrap();
+        let middle = submitter.submit((|a:i32|a).into_task()
+            .to((sink,Pi::PI1).into())).unwrap();
+        let source = submitter.submit((|a:i32|a).into_task()
+            .cond_timeout(Duration::from_millis(10))
+            .to((middle,Pi::PI0).into())).unwrap();
+        // the default of an optional cond is taken instead
+        let optional = submitter.submit((|a:i32|a).into_task()
+            .optional_cond(Pi::PI0, 5, Duration::from_secs(60))).unwrap();
+        submitter.submit((|a:i32|a).into_task()
+            .cond_timeout(Duration::from_millis(10))
+            .to((optional,Pi::PI0).into())).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        for id in [source, middle, sink, optional] {
+            assert!(submitter.c1map.check(id).is_none(), "{id:?}");
+        }
+        // released by the two timers in any order
+        let mut results: Vec<Box<dyn Any>> = (0..2).map(|_|submitter.queue.pop().unwrap().0.run().unwrap()).collect();
+        results.sort_by_key(|r|!r.is::<i32>());
+        assert_eq!(results[0].downcast_ref::<i32>(), Some(&5));
+        let timeout = CondTimeout { taskid: sink, missing: vec![Pi::PI0, Pi::PI1] };
+        assert_eq!(results[1].downcast_ref::<CondTimeout>(), Some(&timeout));
+    }
+}
+
+#[cfg(test)]
+mod test_retry {
+    use std::{sync::{atomic::AtomicUsize, mpsc}, time::Duration};
+    use super::*;
+    use crate::{task::RetryPolicy, Pool, TaskBuildNew};
+
+    #[test]
+    fn test_retry_if() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        let exit = submitter.submit((move|r:Result<usize,usize>|tx.send(r).unwrap()).into_exit_task()).unwrap();
+        let runs = AtomicUsize::new(0);
+        let task = (move||match runs.fetch_add(1, Ordering::Relaxed) {
+                n @ 0..2 => Err(n),
+                n => Ok(n),
+            }).into_task()
+            .retry_if(RetryPolicy::exponential(5, Duration::from_millis(1), Duration::from_millis(5)), Result::is_err)
+            .to((exit,Pi::PI0).into());
+        submitter.submit(task).unwrap();
+        pool.spawn_thread_for(qid);
+        pool.join();
+        // only the final result is passed
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [Ok(2)]);
+    }
+
+    #[test]
+    fn test_retry_panic() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        let exit = submitter.submit((move|r:usize|tx.send(r).unwrap()).into_exit_task()).unwrap();
+        let runs = AtomicUsize::new(0);
+        let task = (move|a:usize|match runs.fetch_add(1, Ordering::Relaxed) {
+                0 => panic!("the first run fails"),
+                n => a+n,
+            }).into_task()
+            .retry(RetryPolicy::fixed(2, Duration::from_millis(1)))
+            .default_cond(Pi::PI0, 10usize)
+            .to((exit,Pi::PI0).into());
+        submitter.submit(task).unwrap();
+        pool.spawn_thread_for(qid);
+        pool.join();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [11]);
+    }
+
+    #[test]
+    fn test_retry_exit() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        // the worker exits only after the final run of the exit task
+        let runs = AtomicUsize::new(0);
+        let task = (move||{
+                let n = runs.fetch_add(1, Ordering::Relaxed);
+                tx.send(n).unwrap();
+                if n == 0 {
+                    panic!("the first run fails");
+                }
+            }).into_exit_task()
+            .retry(RetryPolicy::fixed(2, Duration::from_millis(1)));
+        submitter.submit(task).unwrap();
+        pool.spawn_thread_for(qid);
+        pool.join();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [0, 1]);
+        assert!(submitter.queue.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test_timeout {
+    use std::{sync::mpsc, time::Duration};
+    use super::*;
+    use crate::{CancelToken, Pool, TaskBuildNew};
+
+    #[test]
+    fn test_timeout() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        let target = submitter.submit((|_:i32|{}).into_task()).unwrap();
+        let task = (move||{
+                let token = CancelToken::current();
+                while !token.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                tx.send("cancelled").unwrap();
+                1
+            }).into_task()
+            .timeout(Duration::from_millis(10))
+            .to((target,Pi::PI0).into());
+        submitter.submit(task).unwrap();
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.spawn_thread_for(qid);
+        let c1map = pool.c1map.clone();
+        pool.join();
+        assert_eq!(rx.try_recv(), Ok("cancelled"));
+        // the result of the timed out task is dropped
+        assert_eq!(c1map.check(target), Some(target));
+    }
+}
+
+#[cfg(test)]
+mod test_weighted {
+    use std::sync::mpsc;
+    use crate::{Pool, TaskBuildNew};
+    use super::*;
+
+    #[test]
+    fn test_weighted_round_robin() {
+        let mut pool = Pool::new();
+        let q1 = pool.insert_queue(&Queue::new()).unwrap();
+        let q2 = pool.insert_queue(&Queue::new()).unwrap();
+        let (s1,s2) = (pool.task_submitter(q1).unwrap(),pool.task_submitter(q2).unwrap());
+        let (tx,rx) = mpsc::channel();
+        for _ in 0..8 {
+            let (tx1,tx2) = (tx.clone(),tx.clone());
+            s1.submit((move||tx1.send(1).unwrap()).into_task()).unwrap();
+            s2.submit((move||tx2.send(2).unwrap()).into_task()).unwrap();
+        }
+        pool.spawn_weighted_thread_for(&[(q1,3),(q2,1)]).unwrap();
+        let order: Vec<i32> = (0..8).map(|_|rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        assert_eq!(order.iter().filter(|q|**q==2).count(), 2, "{order:?}");
+        assert!(order[..4].contains(&2), "{order:?}");
+        // the other 2 of q1 go on, then q2 alone has all the turns
+        let rest: Vec<i32> = (0..8).map(|_|rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        assert_eq!(rest.iter().filter(|q|**q==2).count(), 6, "{rest:?}");
+
+        // an idle worker wakes on a task added into any of its queues
+        let tx2 = tx.clone();
+        s2.submit((move||tx2.send(2).unwrap()).into_task()).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(2));
+        s1.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+    }
+
+    #[test]
+    fn test_weighted_late_backlog() {
+        let mut pool = Pool::new();
+        let q1 = pool.insert_queue(&Queue::new()).unwrap();
+        let q2 = pool.insert_queue(&Queue::new()).unwrap();
+        let (s1,s2) = (pool.task_submitter(q1).unwrap(),pool.task_submitter(q2).unwrap());
+        let (tx,rx) = mpsc::channel();
+        // q1 runs alone for a while, q2 banks no credit meanwhile
+        for _ in 0..40 {
+            let tx1 = tx.clone();
+            s1.submit((move||tx1.send(1).unwrap()).into_task()).unwrap();
+        }
+        // the backlog of q2 comes late, added by the last task alone of q1
+        let (tx1,tx2,s2) = (tx.clone(),tx.clone(),s2.clone());
+        s1.submit((move||{
+            for _ in 0..40 {
+                let tx2 = tx2.clone();
+                s2.submit((move||tx2.send(2).unwrap()).into_task()).unwrap();
+            }
+            tx1.send(1).unwrap();
+        }).into_task()).unwrap();
+        for _ in 0..40 {
+            let tx1 = tx.clone();
+            s1.submit((move||tx1.send(1).unwrap()).into_task()).unwrap();
+        }
+        pool.spawn_weighted_thread_for(&[(q1,3),(q2,1)]).unwrap();
+        let order: Vec<i32> = (0..81).map(|_|rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        assert!(order[..41].iter().all(|q|*q==1), "{order:?}");
+        // q2 takes 1 turn of every 4, q1 is not starved by the backlog
+        let next = &order[41..];
+        assert_eq!(next.iter().filter(|q|**q==2).count(), 10, "{next:?}");
+        assert!(next.chunks(4).all(|turns|turns.iter().filter(|q|**q==2).count() == 1), "{next:?}");
+        for _ in 0..40 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        s1.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+    }
+}
+
+#[cfg(test)]
+mod test_deadline {
+    use std::sync::mpsc;
+    use super::*;
+    use crate::{Pool, TaskBuildNew, TaskSubmitter};
+
+    fn run_next(queue:&Queue)->i32 {
+        let (task,_postdo) = queue.pop().unwrap();
+        *task.run().unwrap().downcast::<i32>().unwrap()
+    }
+
+    #[test]
+    fn test_edf_order() {
+        let submitter = TaskSubmitter { qid: 1, queue: Queue::edf(), c1map: C1map::new() };
+        let now = Instant::now();
+        submitter.submit((||3).into_task().deadline(now + Duration::from_secs(3))).unwrap();
+        submitter.submit((||0).into_task()).unwrap();
+        submitter.submit((||1).into_task().deadline(now + Duration::from_secs(1))).unwrap();
+        submitter.submit((||2).into_task().deadline(now + Duration::from_secs(2))).unwrap();
+        let order: Vec<i32> = (0..4).map(|_|run_next(&submitter.queue)).collect();
+        assert_eq!(order, [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_deadline_miss() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::edf()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        let handler = submitter.submit((move|m:DeadlineMissed|tx.send(m.taskid).unwrap()).into_task()).unwrap();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran1 = ran.clone();
+        let task = (move||ran1.store(true, Ordering::SeqCst), TaskId::from(100)).into_task()
+            .deadline(Instant::now())
+            .on_deadline_miss(DeadlineMiss::Report((handler,Pi::PI0).into()));
+        submitter.submit(task).unwrap();
+        pool.spawn_thread_for(qid);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(TaskId::from(100)));
+        assert!(!ran.load(Ordering::SeqCst));
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+    }
+
+    #[test]
+    fn test_exit_deadline_miss() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::edf()).unwrap();
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        pool.subscribe(move|event|if let PoolEvent::ExitTaskRun { taskid, .. } = event {
+            tx.send(*taskid).unwrap();
+        });
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran1 = ran.clone();
+        let exit = (move||ran1.store(true, Ordering::SeqCst), TaskId::from(300)).into_exit_task()
+            .deadline(Instant::now());
+        submitter.submit(exit).unwrap();
+        pool.spawn_thread_for(qid);
+        // the worker exits though its exit task is dropped
+        pool.join();
+        assert!(!ran.load(Ordering::SeqCst));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(TaskId::from(300)));
+    }
+
+    #[test]
+    fn test_inherit_deadline() {
+        let submitter = TaskSubmitter { qid: 1, queue: Queue::edf(), c1map: C1map::new() };
+        let c1q = (1,submitter.queue.clone());
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let sink = submitter.submit((|a:i32|a).into_task().deadline(deadline)).unwrap();
+        let stage = (|a:i32|a, TaskId::from(200)).into_task().inherit_deadline().to((sink,Pi::PI0).into());
+        let stage = submitter.submit(stage).unwrap();
+        submitter.submit((||9).into_task().deadline(deadline + Duration::from_secs(1))).unwrap();
+        assert_eq!(when_ci_comed(&(stage,Pi::PI0).into(), (&1,&TaskId::NONE), submitter.c1map.clone(), c1q), Ok(true));
+        // the stage goes before the task due later than its downstream
+        let (mut task,_postdo) = submitter.queue.pop().unwrap();
+        assert_eq!(task.id(), stage);
+        assert_eq!(task.attr_mut().deadline, Some(deadline));
+    }
+}
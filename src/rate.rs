@@ -0,0 +1,189 @@
+//! ## rate module
+//!
+//! Token-bucket rate limits, on a queue or on the tasks with the same tag.
+//!
+//! A bucket holds at most `n` tokens and is refilled with `n` tokens per interval,
+//! each task started takes one token. A task without a token stays in its queue,
+//! and the worker sleeps until the next token comes rather than spinning.
+//! A tagged task without a token reserves the next free one, and is put aside until it comes.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{queue::{PostDo, Queue}, task::Task};
+
+pub(crate) struct TokenBucket {
+    capacity: u32,
+    per: Duration,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// a full bucket of `n` tokens, refilled with `n` tokens `per` interval
+    pub(crate) fn new(n:u32, per:Duration)->Self {
+        Self { capacity: n, per, tokens: n as f64, last: Instant::now() }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() / self.per.as_secs_f64() * self.capacity as f64;
+        self.tokens = (self.tokens + refill).min(self.capacity as f64);
+        self.last = now;
+    }
+
+    /// how long until the bucket has a token again
+    fn lack(&self)->Duration {
+        self.per.mul_f64((1.0 - self.tokens) / self.capacity.max(1) as f64)
+    }
+
+    /// takes a token, or returns how long until the next one comes.
+    pub(crate) fn try_take(&mut self)->Result<(),Duration> {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.lack())
+        }
+    }
+
+    /// takes a token, or reserves the next one not reserved yet and returns how long until it comes,
+    /// so the tasks waiting for tokens get them one by one in order.
+    pub(crate) fn reserve(&mut self)->Result<(),Duration> {
+        self.refill();
+        let taken = match self.tokens >= 1.0 {
+            true => Ok(()),
+            false => Err(self.lack()),
+        };
+        self.tokens -= 1.0;
+        taken
+    }
+}
+
+/// The rate limits of the tagged tasks, shared by all the queues of a pool.
+#[derive(Clone, Default)]
+pub(crate) struct TagRates(Arc<Mutex<HashMap<&'static str,TokenBucket>>>);
+
+impl TagRates {
+    pub(crate) fn set_limit(&self, tag:&'static str, n:u32, per:Duration) {
+        self.0.lock().unwrap().insert(tag, TokenBucket::new(n, per));
+    }
+
+    /// Lets the tagged task start if a token is taken, or else reserves the next free token for it
+    /// and puts it back to the front of the queue once that token comes, when it starts without another.
+    pub(crate) fn admit(&self, tag:&'static str, mut task:Box<dyn Task+Send>, postdo:Box<PostDo>, queue:&Queue)
+        ->Option<(Box<dyn Task+Send>,Box<PostDo>)>
+    {
+        if std::mem::take(&mut task.attr_mut().rate_reserved) {
+            return Some((task,postdo));
+        }
+        let wait = match self.0.lock().unwrap().get_mut(tag) {
+            Some(bucket) => bucket.reserve(),
+            None => Ok(()),
+        };
+        match wait {
+            Ok(()) => Some((task,postdo)),
+            Err(wait) => {
+                debug!(task_id=task.id(); "task#{:?} with tag '{tag}' is over the rate limit, delayed {wait:?}.", task.id());
+                task.attr_mut().rate_reserved = true;
+                queue.add_later(wait, task, postdo, true);
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let mut bucket = TokenBucket::new(2, Duration::from_millis(100));
+    assert!(bucket.try_take().is_ok());
+    assert!(bucket.try_take().is_ok());
+    let wait = bucket.try_take().unwrap_err();
+    assert!(wait > Duration::from_millis(40) && wait <= Duration::from_millis(50));
+    std::thread::sleep(wait);
+    assert!(bucket.try_take().is_ok());
+
+    // each reservation waits one more interval than the one before
+    let mut bucket = TokenBucket::new(1, Duration::from_millis(100));
+    assert!(bucket.reserve().is_ok());
+    let waits: Vec<Duration> = (0..3).map(|_|bucket.reserve().unwrap_err()).collect();
+    assert!(waits[0] > Duration::from_millis(90) && waits[0] <= Duration::from_millis(100), "{waits:?}");
+    assert!(waits[1] > Duration::from_millis(190) && waits[2] > Duration::from_millis(290), "{waits:?}");
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use super::*;
+    use crate::{Pool, TaskBuildNew};
+
+    fn run_tasks(pool:&mut Pool, qid:usize, tag:Option<&'static str>)->Duration {
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        let start = Instant::now();
+        for _ in 0..8 {
+            let tx = tx.clone();
+            let mut task = (move||tx.send(()).unwrap()).into_task();
+            if let Some(tag) = tag {
+                task = task.tag(tag);
+            }
+            submitter.submit(task).unwrap();
+        }
+        pool.spawn_thread_for(qid);
+        pool.spawn_thread_for(qid);
+        for _ in 0..8 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        let elapsed = start.elapsed();
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        elapsed
+    }
+
+    #[test]
+    fn test_queue_rate_limit() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::with_rate_limit(4, Duration::from_millis(100))).unwrap();
+        // 4 at once, and the other 4 in 100ms
+        let elapsed = run_tasks(&mut pool, qid, None);
+        pool.join();
+        assert!(elapsed >= Duration::from_millis(90), "{elapsed:?}");
+    }
+
+    #[test]
+    fn test_tag_rate_limit() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        pool.set_tag_rate_limit("api", 4, Duration::from_millis(100));
+        let elapsed = run_tasks(&mut pool, qid, Some("api"));
+        pool.join();
+        assert!(elapsed >= Duration::from_millis(90), "{elapsed:?}");
+    }
+
+    #[test]
+    fn test_tag_rate_order() {
+        let mut pool = Pool::new();
+        let qid = pool.insert_queue(&Queue::new()).unwrap();
+        pool.set_tag_rate_limit("api", 1, Duration::from_millis(20));
+        let submitter = pool.task_submitter(qid).unwrap();
+        let (tx,rx) = mpsc::channel();
+        for i in 0..6 {
+            let tx = tx.clone();
+            submitter.submit((move||tx.send((i,Instant::now())).unwrap()).into_task().tag("api")).unwrap();
+        }
+        let start = Instant::now();
+        pool.spawn_thread_for(qid);
+        let runs: Vec<(i32,Instant)> = (0..6).map(|_|rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        submitter.submit((||{}).into_exit_task()).unwrap();
+        pool.join();
+        // the tasks over the rate start in order, each with its own token
+        assert_eq!(runs.iter().map(|(i,_)|*i).collect::<Vec<_>>(), [0,1,2,3,4,5]);
+        for (i,(_,at)) in runs.iter().enumerate() {
+            assert!(at.duration_since(start) >= Duration::from_millis(20 * i as u64).saturating_sub(Duration::from_millis(5)), "{i}");
+        }
+    }
+}
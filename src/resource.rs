@@ -0,0 +1,202 @@
+//! ## resource module
+//!
+//! Named resource limits, e.g. at most 4 tasks holding a database connection at a time.
+//!
+//! A task declares what it requires by `TaskBuild::requires()`. When popped by a worker,
+//! it either acquires all the tokens at once, or is parked aside without holding the worker,
+//! and goes back to the front of its queue once some of the tokens are released.
+//! A task requiring more tokens than the limit is refused by `submit()`, or dropped once popped
+//! if the limit is lowered meanwhile, rather than parked for ever.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::{event::PoolEvent, queue::{PostDo, Queue}, stuck::Delayed, task::Task};
+
+/// a parked task with its queue, counted as delayed in the pool meanwhile
+type Parked = (Box<dyn Task+Send>,Box<PostDo>,Queue,Option<Delayed>);
+
+#[derive(Default)]
+struct Slot {
+    limit: usize,
+    used: usize,
+    /// the tasks waiting for the tokens of this resource
+    parked: VecDeque<Parked>,
+}
+
+/// The resource limits shared by all the queues of a pool.
+#[derive(Clone, Default)]
+pub(crate) struct Limits(Arc<Mutex<HashMap<&'static str,Slot>>>);
+
+impl Limits {
+    pub(crate) fn set_limit(&self, name:&'static str, limit:usize) {
+        let mut lock = self.0.lock().unwrap();
+        let slot = lock.entry(name).or_default();
+        slot.limit = limit;
+        let parked = std::mem::take(&mut slot.parked);
+        drop(lock);
+        unpark(parked);
+    }
+
+    /// the first resource of which more tokens are required than its limit, with the count and the limit
+    pub(crate) fn over_limit(&self, requires:&[(&'static str,usize)])->Option<(&'static str,usize,usize)> {
+        let lock = self.0.lock().unwrap();
+        requires.iter().find_map(|(name,n)|
+            lock.get(name).filter(|slot|*n > slot.limit).map(|slot|(*name,*n,slot.limit))
+        )
+    }
+
+    /// Acquires the tokens required by the task, all or nothing.
+    ///
+    /// Returns the task with the permit holding the tokens,
+    /// or None if the task is parked until some tokens are released,
+    /// or dropped as it requires more than the limit.
+    pub(crate) fn acquire(&self, mut task:Box<dyn Task+Send>, postdo:Box<PostDo>, queue:&Queue)
+        ->Option<(Box<dyn Task+Send>,Box<PostDo>,Permit)>
+    {
+        let requires = task.attr_mut().requires.clone();
+        if let Some((_name,_n,_limit)) = self.over_limit(&requires) {
+            let taskid = task.id();
+            error!(task_id=taskid; "task#{taskid:?} requires {_n} of resource '{_name}' beyond its limit {_limit}, and is dropped.");
+            queue.metrics().cancelled(task.attr_mut().tag);
+            queue.emit(|qid|PoolEvent::Cancelled { taskid, qid });
+            return None;
+        }
+        let mut lock = self.0.lock().unwrap();
+        let unavailable = requires.iter().find(|(name,n)|
+            lock.get(name).is_some_and(|slot|slot.used + n > slot.limit)
+        );
+        if let Some((name,_n)) = unavailable {
+            debug!(task_id=task.id(); "task#{:?} is parked, waiting for {_n} of resource '{name}'.", task.id());
+            let slot = lock.get_mut(name).unwrap();
+            slot.parked.push_back((task,postdo,queue.clone(),queue.delayed()));
+            return None;
+        }
+        for (name,n) in requires.iter() {
+            if let Some(slot) = lock.get_mut(name) {
+                slot.used += n;
+            } else {
+                warn!(task_id=task.id(); "task#{:?} requires resource '{name}' without a limit, taken as unlimited.", task.id());
+            }
+        }
+        drop(lock);
+        Some((task,postdo,Permit {limits: self.clone(), requires}))
+    }
+
+    fn release(&self, requires:&[(&'static str,usize)]) {
+        let mut lock = self.0.lock().unwrap();
+        let mut parked = VecDeque::new();
+        for (name,n) in requires {
+            if let Some(slot) = lock.get_mut(name) {
+                slot.used = slot.used.saturating_sub(*n);
+                parked.append(&mut slot.parked);
+            }
+        }
+        drop(lock);
+        unpark(parked);
+    }
+}
+
+/// the parked tasks try again from the front of their queues, in the order they were parked
+fn unpark(parked:VecDeque<Parked>) {
+    for (task,postdo,queue,delayed) in parked.into_iter().rev() {
+        queue.add_boxtask_front(task, postdo);
+        drop(delayed);
+    }
+}
+
+/// The tokens held by a running task, released when dropped,
+/// that is, when the run completes, panics or is cancelled.
+pub(crate) struct Permit {
+    limits: Limits,
+    requires: Vec<(&'static str,usize)>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limits.release(&self.requires);
+    }
+}
+
+#[test]
+fn test_limits() {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc}, time::Duration};
+    use crate::{Pool, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    pool.set_resource_limit("db", 2);
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let (tx,rx) = mpsc::channel();
+    for _ in 0..8 {
+        let (running,max_running,tx) = (running.clone(),max_running.clone(),tx.clone());
+        let task = (move||{
+            let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(n, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            tx.send(()).unwrap();
+        }).into_task().requires("db", 1);
+        submitter.submit(task).unwrap();
+    }
+    for _ in 0..4 {
+        pool.spawn_thread_for(qid);
+    }
+    for _ in 0..8 {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    for _ in 0..4 {
+        submitter.submit((||{}).into_exit_task()).unwrap();
+    }
+    pool.join();
+    assert_eq!(max_running.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_parked() {
+    use std::{sync::mpsc, time::Duration};
+    use crate::{Pi, Pool, PoolEvent, TaskBuildNew, TaskError, TaskId};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    pool.set_resource_limit("db", 1);
+    // never runs, but refused at once
+    let r = submitter.submit((||{}).into_task().requires("db", 2));
+    assert!(matches!(r, Err(TaskError::OverLimit { resource: "db", requires: 2, limit: 1 })), "{r:?}");
+
+    // the token is held here, so the task is parked, and the pool with a parked task is not idle
+    let (permit_task,postdo,permit) = pool.shared.limits.acquire(
+        Box::new((||{}).into_task().requires("db", 1).0), Box::new(|_|()), &submitter.queue).unwrap();
+    drop((permit_task,postdo));
+    submitter.submit((|_:i32|{}, TaskId::from(1)).into_task()).unwrap();
+    let (tx,rx) = mpsc::channel();
+    submitter.submit((move||tx.send(()).unwrap()).into_task().requires("db", 1)).unwrap();
+    pool.spawn_thread_for(qid);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(rx.try_recv().is_err());
+    assert!(pool.stuck_tasks().is_empty());
+    drop(permit);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(pool.stuck_tasks().len(), 1);
+
+    // the limit lowered after the submission, the task is dropped when popped
+    let (tx,rx) = mpsc::channel();
+    pool.subscribe(move|event|if let PoolEvent::Cancelled { taskid, .. } = event {
+        tx.send(*taskid).unwrap();
+    });
+    let (hold_tx,hold_rx) = mpsc::channel::<()>();
+    submitter.submit((move||hold_rx.recv().unwrap()).into_task()).unwrap();
+    let dropped = submitter.submit((||{}, TaskId::from(2)).into_task().requires("db", 1)).unwrap();
+    pool.set_resource_limit("db", 0);
+    hold_tx.send(()).unwrap();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(dropped));
+    submitter.deliver((TaskId::from(1),Pi::PI0).into(), 0).unwrap();
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+}
@@ -0,0 +1,194 @@
+//! ## sim module
+//!
+//! Runs the tasks of a pool on the calling thread one at a time, in an order drawn from a seed,
+//! to test a task graph deterministically, see `SimPool`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::{queue::run_popped, task::TaskId, Pool, Queue, TaskSubmitter};
+
+/// A pool without workers, whose tasks run on the calling thread by `step()` or `run_until_idle()`.
+///
+/// Each step takes one of the tasks ready in all the queues, drawn from the seed, and runs it to completion,
+/// passing its result on before the next step. Any ready task may be drawn, not only the front ones,
+/// which covers the orders the tasks complete in a pool with enough workers,
+/// so a graph passing with many seeds does not depend on the interleaving of its tasks.
+/// The same seed with the same submissions runs the tasks in the same order.
+///
+/// The timers still run on the clock, so a task delayed by a retry, a rate limit or an optional cond
+/// is ready only once its delay has passed, and the rate limit of a queue is not applied by a step.
+///
+/// # Example:
+/// ```rust
+/// # use taskorch::{Pi, Queue, SimPool, TaskBuildNew as _, TaskId};
+/// SimPool::explore(0..100, |sim|{
+///     let qid = sim.insert_queue(&Queue::new()).unwrap();
+///     let submitter = sim.task_submitter(qid).unwrap();
+///     let sum = submitter.submit((|a:i32,b:i32|assert_eq!(a+b, 3), TaskId::from(1)).into_task()).unwrap();
+///     submitter.submit((||1).into_task().to((sum,Pi::PI0).into())).unwrap();
+///     submitter.submit((||2).into_task().to((sum,Pi::PI1).into())).unwrap();
+///     sim.run_until_idle();
+///     assert_eq!(sim.history().last(), Some(&sum));
+/// });
+/// ```
+pub struct SimPool {
+    pool: Pool,
+    seed: u64,
+    state: u64,
+    history: Vec<TaskId>,
+}
+
+impl SimPool {
+    pub fn new(seed:u64)->Self {
+        Self { pool: Pool::new(), seed, state: seed, history: Vec::new() }
+    }
+
+    /// The seed the order of the tasks is drawn from, to replay it by `SimPool::new()`.
+    pub fn seed(&self)->u64 {
+        self.seed
+    }
+
+    pub fn insert_queue(&mut self, queue:&Queue)->Option<usize> {
+        self.pool.insert_queue(queue)
+    }
+
+    pub fn task_submitter(&self, qid:usize)->Option<TaskSubmitter> {
+        self.pool.task_submitter(qid)
+    }
+
+    /// The pool behind, e.g. to list its pending tasks or to subscribe to its events.
+    pub fn pool(&self)->&Pool {
+        &self.pool
+    }
+
+    /// Runs one of the tasks ready in the queues, drawn from the seed, and returns its id,
+    /// `None` if no task is ready.
+    ///
+    /// The task taken may be put aside rather than run, e.g. over a tag rate or waiting for a resource,
+    /// its id is returned still but not recorded in `history()`.
+    ///
+    /// A panic of the task goes on to the caller, after its retries if any.
+    pub fn step(&mut self)->Option<TaskId> {
+        let mut queues: Vec<(usize,Queue)> = self.pool.queues.iter().map(|(qid,queue)|(*qid,queue.clone())).collect();
+        queues.sort_by_key(|(qid,_)|*qid);
+        let ready: usize = queues.iter().map(|(_,queue)|queue.len()).sum();
+        if ready == 0 {
+            return None;
+        }
+        let mut i = (self.next() % ready as u64) as usize;
+        for (_qid,queue) in queues {
+            let len = queue.len();
+            if i >= len {
+                i -= len;
+                continue;
+            }
+            let (task,postdo) = queue.take(i)?;
+            let taskid = task.id();
+            debug!(task_id=taskid, qid=_qid; "step#{} takes task#{taskid:?} from Q#{_qid}.", self.history.len());
+            if run_popped(task, postdo, &queue).is_some() {
+                self.history.push(taskid);
+            }
+            return Some(taskid);
+        }
+        None
+    }
+
+    /// Runs the tasks until none is ready, returns the count of the tasks run.
+    pub fn run_until_idle(&mut self)->usize {
+        let before = self.history.len();
+        while self.step().is_some() {}
+        self.history.len() - before
+    }
+
+    /// The ids of the tasks run by the steps so far, in order,
+    /// a task retried or polled again later is recorded once done.
+    pub fn history(&self)->&[TaskId] {
+        &self.history
+    }
+
+    /// Runs `f` on a new `SimPool` with each seed, and panics with the first seed `f` panics with,
+    /// which is replayed by `SimPool::new(seed)`.
+    pub fn explore(seeds:impl IntoIterator<Item = u64>, f:impl Fn(&mut SimPool)) {
+        for seed in seeds {
+            let mut sim = SimPool::new(seed);
+            if let Err(panic) = catch_unwind(AssertUnwindSafe(||f(&mut sim))) {
+                let reason = panic.downcast_ref::<&str>().copied()
+                    .or(panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("a panic");
+                panic!("the simulation fails with the seed {seed}, replay it by SimPool::new({seed}): {reason}");
+            }
+        }
+    }
+
+    /// splitmix64
+    fn next(&mut self)->u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[test]
+fn test_sim_pool() {
+    use std::sync::{Arc, Mutex};
+    use crate::{Pi, TaskBuildNew};
+
+    // task#3 gets the values of task#1 and task#2, which race to append to the log
+    let run = |seed:u64|{
+        let mut sim = SimPool::new(seed);
+        let qid = sim.insert_queue(&Queue::new()).unwrap();
+        let q2 = sim.insert_queue(&Queue::new()).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let submitter = sim.task_submitter(qid).unwrap();
+        let t3 = submitter.submit((|a:i32,b:i32|a*10+b, TaskId::from(3)).into_task()).unwrap();
+        let l = log.clone();
+        submitter.submit((move||{ l.lock().unwrap().push(1); 1 }, TaskId::from(1)).into_task().to((t3,Pi::PI0).into())).unwrap();
+        let l = log.clone();
+        sim.task_submitter(q2).unwrap()
+            .submit((move||{ l.lock().unwrap().push(2); 2 }, TaskId::from(2)).into_task().to((t3,Pi::PI1).into())).unwrap();
+        assert_eq!(sim.run_until_idle(), 3);
+        assert_eq!(sim.step(), None);
+        assert_eq!(sim.history().last(), Some(&t3));
+        let log = log.lock().unwrap().clone();
+        (sim.history().to_vec(), log)
+    };
+    // the same seed, the same order
+    assert_eq!(run(7), run(7));
+    // both orders of the race come up
+    let orders: Vec<_> = (0..32).map(|seed|run(seed).1).collect();
+    assert!(orders.contains(&vec![1,2]) && orders.contains(&vec![2,1]), "{orders:?}");
+
+    // the failing seed is named
+    let failed = catch_unwind(||SimPool::explore(0..32, |sim|{
+        let seed = sim.seed();
+        let (history,log) = run(seed);
+        assert_eq!(history.len(), 3);
+        assert_eq!(log, [1,2], "task#1 is expected to run first");
+    }));
+    let panic = failed.unwrap_err();
+    let message = panic.downcast_ref::<String>().unwrap();
+    let seed = orders.iter().position(|order|order == &[2,1]).unwrap();
+    assert!(message.starts_with(&format!("the simulation fails with the seed {seed}, ")), "{message}");
+}
+
+#[test]
+fn test_sim_put_aside() {
+    use std::time::Duration;
+    use crate::TaskBuildNew;
+
+    // the second tagged task is over the rate, taken by a step but not run
+    let mut sim = SimPool::new(0);
+    let qid = sim.insert_queue(&Queue::new()).unwrap();
+    sim.pool().set_tag_rate_limit("slow", 1, Duration::from_secs(3600));
+    let submitter = sim.task_submitter(qid).unwrap();
+    for id in [1,2] {
+        submitter.submit((||{}, TaskId::from(id)).into_task().tag("slow")).unwrap();
+    }
+    assert!(sim.step().is_some());
+    assert!(sim.step().is_some());
+    assert_eq!(sim.step(), None);
+    assert_eq!(sim.history().len(), 1);
+    assert_eq!(sim.run_until_idle(), 0);
+}
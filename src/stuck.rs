@@ -0,0 +1,210 @@
+//! ## stuck module
+//!
+//! Detects the conditional tasks waiting for conds which nothing is going to deliver,
+//! the case where `Pool::join()` waits forever: no worker runs a task, the queues are empty,
+//! no task is delayed by a timer, and yet some tasks are waiting.
+
+use std::{
+    fmt,
+    sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc, RwLock, Weak},
+    time::Duration,
+};
+
+use crate::{
+    queue::{C1map, WeakQueue},
+    task::{Pi, TaskId},
+    timer,
+};
+
+/// A conditional task waiting for conds while nothing in the pool is going on, see `Pool::watch_stuck()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StuckTask {
+    pub taskid: TaskId,
+    /// the queue which the task is submitted to
+    pub qid: usize,
+    /// the conds not delivered yet
+    pub missing: Vec<MissingCond>,
+}
+
+/// A cond which a stuck task misses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingCond {
+    pub pi: Pi,
+    /// the type name of the cond, see `std::any::type_name()`
+    pub type_name: &'static str,
+    /// the waiting tasks built with `to()` this cond, stuck themselves,
+    /// empty if no known task is going to deliver it
+    pub producers: Vec<TaskId>,
+}
+
+impl fmt::Display for StuckTask {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+        write!(f, "task#{:?} on Q#{} misses", self.taskid, self.qid)?;
+        for (i,cond) in self.missing.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(f, "{sep} cond#{} <{}>", cond.pi.0, cond.type_name)?;
+            match cond.producers.as_slice() {
+                [] => write!(f, " with no producer")?,
+                producers => write!(f, " from the stuck {producers:?}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+type Report = Arc<dyn Fn(&[StuckTask]) + Send + Sync>;
+
+/// What is going on in a pool, besides the tasks ready in the queues and those waiting for conds.
+#[derive(Default)]
+pub(crate) struct Activity {
+    /// the tasks running on the workers
+    running: AtomicUsize,
+    /// the tasks started so far, to tell a pool idle for a while from one idle for a moment
+    started: AtomicU64,
+    /// the tasks put aside before added into their queue again,
+    /// held by a timer for a retry or a rate limit, parked for a resource or pending as awaited
+    delayed: AtomicUsize,
+    queues: RwLock<Vec<WeakQueue>>,
+}
+
+/// A task counted as running until dropped.
+pub(crate) struct Counted<'a>(&'a AtomicUsize);
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// A task counted as delayed until dropped, held along with the task put aside.
+pub(crate) struct Delayed(Arc<Activity>);
+
+impl Drop for Delayed {
+    fn drop(&mut self) {
+        self.0.delayed.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Activity {
+    pub(crate) fn register(&self, queue:WeakQueue) {
+        self.queues.write().unwrap().push(queue);
+    }
+
+    /// counts a task running until the guard is dropped, also when the task panics
+    pub(crate) fn run(&self)->Counted<'_> {
+        self.started.fetch_add(1, Ordering::AcqRel);
+        self.running.fetch_add(1, Ordering::AcqRel);
+        Counted(&self.running)
+    }
+
+    pub(crate) fn delay(self:&Arc<Self>)->Delayed {
+        self.delayed.fetch_add(1, Ordering::AcqRel);
+        Delayed(self.clone())
+    }
+
+    /// the count of the tasks put aside, see `Delayed`
+    pub(crate) fn delayed(&self)->usize {
+        self.delayed.load(Ordering::Acquire)
+    }
+
+    fn is_idle(&self)->bool {
+        self.running.load(Ordering::Acquire) == 0
+            && self.delayed.load(Ordering::Acquire) == 0
+            && self.queues.read().unwrap().iter().all(|queue|queue.depth() == 0)
+    }
+}
+
+/// The stuck tasks if the pool is idle at the moment, see `Pool::stuck_tasks()`.
+pub(crate) fn stuck_now(activity:&Activity, c1map:&C1map)->Vec<StuckTask> {
+    match activity.is_idle() {
+        true => c1map.stuck(),
+        false => Vec::new(),
+    }
+}
+
+/// Inspects the pool every interval on the timer, as long as the pool is alive,
+/// and passes the stuck tasks to `f` once the pool has stayed idle for a whole interval.
+/// They are passed once until the pool moves on.
+pub(crate) fn watch(activity:&Arc<Activity>, c1map:C1map, every:Duration, f:Report) {
+    let watch = Watch { activity: Arc::downgrade(activity), c1map, every, f, idle_since: None, reported: false };
+    timer::schedule(every, move||watch.tick());
+}
+
+struct Watch {
+    activity: Weak<Activity>,
+    c1map: C1map,
+    every: Duration,
+    f: Report,
+    /// the count of the tasks started, when the pool was found idle at the last tick
+    idle_since: Option<u64>,
+    reported: bool,
+}
+
+impl Watch {
+    fn tick(mut self) {
+        let Some(activity) = self.activity.upgrade() else {
+            // the pool and its queues are gone
+            return;
+        };
+        let started = activity.started.load(Ordering::Acquire);
+        let idle = activity.is_idle();
+        drop(activity);
+        if idle && self.idle_since == Some(started) {
+            if !self.reported {
+                let stuck = self.c1map.stuck();
+                if !stuck.is_empty() {
+                    warn!("{} tasks are stuck waiting for conds while the pool is idle.", stuck.len());
+                    (self.f)(&stuck);
+                    self.reported = true;
+                }
+            }
+        } else {
+            self.reported = false;
+        }
+        self.idle_since = idle.then_some(started);
+        let every = self.every;
+        timer::schedule(every, move||self.tick());
+    }
+}
+
+#[test]
+fn test_stuck() {
+    use std::sync::{mpsc, Mutex};
+    use crate::{Pool, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let (tx,rx) = mpsc::channel();
+    let reports = Arc::new(Mutex::new(0));
+    let r = reports.clone();
+    pool.watch_stuck(Duration::from_millis(10), move|stuck|{
+        *r.lock().unwrap() += 1;
+        tx.send(stuck.to_vec()).unwrap();
+    });
+    // task#2 gets cond#0 from task#1, which waits for a cond nobody delivers
+    let t2 = submitter.submit((|_a:i32,_b:&'static str|{}, TaskId::from(2)).into_task()).unwrap();
+    let t1 = submitter.submit((|a:u8|a as i32, TaskId::from(1)).into_task().to((t2,Pi::PI0).into())).unwrap();
+    submitter.submit((|a:i32|a, TaskId::from(3)).into_task()).unwrap();
+    submitter.submit((||7, TaskId::from(4)).into_task().to((TaskId::from(3),Pi::PI0).into())).unwrap();
+    pool.spawn_thread_for(qid);
+
+    let mut stuck = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    stuck.sort_by_key(|task|task.taskid.0);
+    assert_eq!(stuck, [
+        StuckTask { taskid: t1, qid, missing: vec![
+            MissingCond { pi: Pi::PI0, type_name: "u8", producers: vec![] },
+        ]},
+        StuckTask { taskid: t2, qid, missing: vec![
+            MissingCond { pi: Pi::PI0, type_name: "i32", producers: vec![t1] },
+            MissingCond { pi: Pi::PI1, type_name: "&str", producers: vec![] },
+        ]},
+    ]);
+    assert_eq!(stuck[1].to_string(), "task#TaskId(2) on Q#1 misses cond#0 <i32> from the stuck [TaskId(1)], cond#1 <&str> with no producer");
+    assert_eq!(pool.stuck_tasks().len(), 2);
+    // reported once while the pool stays stuck
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(*reports.lock().unwrap(), 1);
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+}
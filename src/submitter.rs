@@ -1,24 +1,43 @@
 use crate::{
     curry::CallOnce,
+    event::PoolEvent,
     meta::{Fndecl, Identical},
     queue::{when_ci_comed, C1map, WhenTupleComed},
     task::{
-        Task, TaskBuild, TaskCurrier, TaskMap,
+        CondAddr, NullMapFn, RofCurrier, Task, TaskBuild, TaskCurrier, TaskMap,
         TaskId, taskid_next
     },
     Queue,
-    log::{Level,LEVEL},
+    log::{LogLevel,LEVEL},
 };
 
-use std::{any::{Any, TypeId}, fmt::Debug};
+use std::{any::{Any, TypeId}, fmt::Debug, sync::mpsc::Receiver, thread::{self, JoinHandle}};
+
+use crate::{delivery::DeliveryError, future::{Resolve, TaskFuture}};
 
 #[derive(Debug)]
 pub enum TaskError {
     /// when submit task, if the id has already existed in waitQueue.
     TaskIdAlreadyExists(TaskId),
+    /// the task ended without a result, see `TaskSubmitter::submit_async()`:
+    /// it panicked, timed out, missed its deadline or was given up waiting for its conds.
+    Dropped(TaskId),
+    /// the task has run, but its result is not delivered to its `to()` target, see `TaskSubmitter::submit_async()`.
+    Undelivered(DeliveryError),
+    /// the task requires more tokens of the resource than its limit, it would never run, see `TaskBuild::requires()`.
+    OverLimit { resource:&'static str, requires:usize, limit:usize },
 }
 type SummitResult = Result<TaskId,TaskError>;
 
+/// The state of the target task after its cond is filled by `TaskSubmitter::deliver()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivered {
+    /// all the conds are filled, the task is added into the queue.
+    Released,
+    /// the task is still waiting for other conds.
+    Waiting,
+}
+
 /// Handles task submission to a specific queue
 #[derive(Clone)]
 pub struct TaskSubmitter {
@@ -77,28 +96,107 @@ impl TaskSubmitter {
         MapR: Send + 'static,
         MapFn::R: WhenTupleComed,
     {
+        self.submit_resolved(TaskBuild(task,map), None)
+    }
+
+    /// Submits a task like `submit()`, returns the future of its result.
+    ///
+    /// The future is ready once the task has run, with its result even if passed on by `to()`,
+    /// or with the error if the task is not submitted, ends without a result, see `TaskError::Dropped`,
+    /// or its result is not delivered to its `to()` target, see `TaskError::Undelivered`.
+    /// A body built with `awaited()` is polled by the workers, which run other tasks while it is pending.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Pool, Queue, TaskBuildNew as _};
+    /// # let mut pool = Pool::new();
+    /// # let qid = pool.insert_queue(&Queue::new()).unwrap();
+    /// let submitter = pool.task_submitter(qid).unwrap();
+    /// let future = submitter.submit_async((||async { 1+1 }).into_task().awaited());
+    /// // `future.await` in an async context
+    /// # drop(future);
+    /// ```
+    #[allow(private_bounds)]
+    pub fn submit_async<C>(&self,taskbuild:TaskBuild<C,NullMapFn<C::Ret>,()>)->TaskFuture<C::Ret>
+        where
+        TaskCurrier<C>: Task,
+        C: CallOnce<R = <C as RofCurrier>::Ret> + RofCurrier + Send + 'static,
+        C::Ret: 'static + Debug + Send,
+        NullMapFn<C::Ret>: Fndecl<(C::Ret,),()> + Send + 'static,
+        <NullMapFn<C::Ret> as Fndecl<(C::Ret,),()>>::Pt: From<(C::Ret,)>,
+        <NullMapFn<C::Ret> as Fndecl<(C::Ret,),()>>::Pt: Identical<(C::Ret,)>,
+        <NullMapFn<C::Ret> as Fndecl<(C::Ret,),()>>::R: WhenTupleComed,
+    {
+        let future = TaskFuture::new();
+        let resolve = |id:TaskId|{
+            let resolver = future.resolver(id);
+            Box::new(move|r:Result<Box<dyn Any>,TaskError>|match r.map(|r|r.downcast::<C::Ret>()) {
+                Ok(Ok(r)) => resolver.resolve(*r),
+                Ok(Err(_)) => drop(resolver),
+                Err(e) => resolver.fail(e),
+            }) as Resolve
+        };
+        match self.submit_resolved(taskbuild, Some(&resolve)) {
+            Ok(_) => future,
+            Err(e) => TaskFuture::failed(e),
+        }
+    }
+
+    /// `resolve` makes the callback to take the result of the task, dropped if the task ends without one.
+    #[allow(private_bounds)]
+    fn submit_resolved<C,MapFn,MapR>(&self,TaskBuild(task,map):TaskBuild<C,MapFn,MapR>, resolve:Option<&dyn Fn(TaskId)->Resolve>)->SummitResult
+        where
+        TaskCurrier<C>: Task,
+        C: CallOnce + Send + 'static,
+        C::R: 'static + Debug,
+        MapFn: Fndecl<(C::R,),MapR> + Send + 'static,
+        MapFn::Pt: From<(<C as CallOnce>::R,)>,
+        MapFn::Pt: Identical<(<C as CallOnce>::R,)>,
+        MapR: Send + 'static,
+        MapFn::R: WhenTupleComed,
+    {
+        let mut task = task;
+        if let Some((resource,requires,limit)) = self.queue.over_limit(&task.attr.requires) {
+            error!("the task requires {requires} of resource '{resource}' beyond its limit {limit}, and is refused.");
+            return Err(TaskError::OverLimit { resource, requires, limit });
+        }
+        task.attr.resolved = resolve.is_some();
+        match &map {
+            TaskMap::To(ca) => task.attr.next = Some(*ca),
+            TaskMap::ToMany(..) => task.attr.fans_out = true,
+            TaskMap::None => (),
+        }
         let mk_postdo = |id:TaskId| {
             let c1map = self.c1map.clone();
             let c1queue = (self.qid,self.queue.clone());
+            let resolve = resolve.map(|resolve|resolve(id));
             let postdo = move |r: Box<dyn Any>| {
                 let r_from = &id;
                 match map {
-                    TaskMap::None => return,
+                    TaskMap::None => if let Some(resolve) = resolve {
+                        resolve(Ok(r));
+                    },
                     // to single condaddr
                     TaskMap::To(to) => {
                         let _actual_type = r.type_id();
                         let Ok(r) = r.downcast::<C::R>() else {
                             let _expected_type = TypeId::of::<C::R>();
                             let _expected_type_name = std::any::type_name::<C::R>();
-                            error!(
+                            error!(from_task=*r_from, type_name=_expected_type_name;
                                 "task return value downcast failed: expected {}, got {:?}",
                                 _expected_type_name, _actual_type
                             );
                             panic!("failed to conver to R type");
                             // return;
                         };
-                        let r: &C::R = &*r;
-                        when_ci_comed(&to, (r,r_from), c1map, c1queue);
+                        // a zero-sized result is not passed on, only taken by the future
+                        let delivered = match std::mem::size_of::<C::R>() {
+                            0 => Ok(false),
+                            _ => when_ci_comed(&to, (&*r,r_from), c1map, c1queue),
+                        };
+                        if let Some(resolve) = resolve {
+                            resolve(delivered.map(|_|r as Box<dyn Any>).map_err(TaskError::Undelivered));
+                        }
                     },
                     // to multi-condaddr
                     TaskMap::ToMany(mapfn, _) => {
@@ -106,7 +204,7 @@ impl TaskSubmitter {
                         let Ok(r) = r.downcast::<C::R>() else {
                             let _expected_type = TypeId::of::<C::R>();
                             let _expected_type_name = std::any::type_name::<C::R>();
-                            error!(
+                            error!(from_task=*r_from, type_name=_expected_type_name;
                                 "task return value downcast failed: expected {}, got {:?}",
                                 _expected_type_name, _actual_type
                             );
@@ -129,7 +227,7 @@ impl TaskSubmitter {
 
         // without parameter
         if 0 == task.currier.count() {
-            if LEVEL >= Level::Warn {
+            if LEVEL >= LogLevel::Warn {
                 if let TaskId(Some(_id)) = task.id {
                     warn!("Ignore the taskid {_id:?}: no conditions found for this task.");
                 }
@@ -137,7 +235,7 @@ impl TaskSubmitter {
             // if id is set we will check whether it is conflicted in map queue.
             // if let Some(id) = task.id {
                 if self.c1map.check(task.id).is_some() {
-                    error!("task#{:?} has existed in queue!!",task.id);
+                    error!(task_id=task.id; "task#{:?} has existed in queue!!",task.id);
                     return Err(TaskError::TaskIdAlreadyExists(task.id))
                 }
             // }
@@ -145,8 +243,10 @@ impl TaskSubmitter {
             let taskid = task.id;
             let task = Box::new(task);
             let postdo = Box::new(mk_postdo(taskid));
+            self.queue.metrics().submitted(task.attr.tag);
+            self.queue.emit(|qid|PoolEvent::Submitted { taskid, qid });
             self.queue.add_boxtask(task,postdo);
-            debug!("task#{:?} added into Q#{}", taskid, self.qid);
+            debug!(task_id=taskid, qid=self.qid; "task#{:?} added into Q#{}", taskid, self.qid);
             Ok(taskid)
         } else { // with parameters
             let mut task = task;
@@ -157,17 +257,90 @@ impl TaskSubmitter {
                 unreachable!("task id has feeded in nonzero @A");
             };
             let postdo = Box::new(mk_postdo(task.id));
-            let id = self.c1map.try_insert(task, postdo, taskid);
+            let tag = task.attr.tag;
+            let id = self.c1map.try_insert(task, postdo, taskid, (self.qid,self.queue.clone()));
             if id.is_some() {
                 debug_assert_eq!(Some(taskid),id);
-                debug!("cond-task#{taskid:?} added into waitQueue");
+                self.queue.metrics().submitted(tag);
+                debug!(task_id=taskid; "cond-task#{taskid:?} added into waitQueue");
                 Ok(TaskId(id))
             } else {
-                error!("cond-task#{taskid:?} is duplicated and can not be added into waitQueue!");
+                error!(task_id=taskid; "cond-task#{taskid:?} is duplicated and can not be added into waitQueue!");
                 Err(TaskError::TaskIdAlreadyExists(TaskId(id)))
             }
         }
     }
+    /// Fills the cond `ca` of a waiting task with `value` from outside the pool, e.g. a network thread,
+    /// as if delivered by a task `to()` it. The task released goes into the queue of this submitter.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Delivered, Pi, Pool, Queue, TaskBuildNew as _, TaskId};
+    /// # let mut pool = Pool::new();
+    /// # let qid = pool.insert_queue(&Queue::new()).unwrap();
+    /// let submitter = pool.task_submitter(qid).unwrap();
+    /// let id = submitter.submit((|port:u16, host:String|println!("{host}:{port}"), TaskId::from(1)).into_task()).unwrap();
+    /// assert_eq!(submitter.deliver((id,Pi::PI0).into(), 8080u16), Ok(Delivered::Waiting));
+    /// assert_eq!(submitter.deliver((id,Pi::PI1).into(), "localhost".to_string()), Ok(Delivered::Released));
+    /// assert!(submitter.deliver((id,Pi::PI1).into(), "again".to_string()).is_err());
+    /// ```
+    pub fn deliver<T:'static+Debug>(&self, ca:CondAddr, value:T)->Result<Delivered,DeliveryError> {
+        match when_ci_comed(&ca, (&value,&TaskId::NONE), self.c1map.clone(), (self.qid,self.queue.clone()))? {
+            true => Ok(Delivered::Released),
+            false => Ok(Delivered::Waiting),
+        }
+    }
+
+    /// Delivers each message received from `rx` to the cond `ca` on a thread, until `rx` is disconnected,
+    /// so a channel drives the graph. A message rejected, e.g. before the target is submitted again, is dropped.
+    ///
+    /// Returns the thread, joined to the count of the messages delivered.
+    pub fn deliver_from<T:'static+Debug+Send>(&self, rx:Receiver<T>, ca:CondAddr)->JoinHandle<usize> {
+        self.stream_from(rx, move|_|ca)
+    }
+
+    /// Delivers each message received from `rx` to a fresh slot on a thread, until `rx` is disconnected.
+    /// `slot` is called per message to submit the task to fill, and returns the address of its cond.
+    ///
+    /// Returns the thread, joined to the count of the messages delivered.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::sync::mpsc;
+    /// # use taskorch::{Pi, Pool, Queue, TaskBuildNew as _};
+    /// # let mut pool = Pool::new();
+    /// # let qid = pool.insert_queue(&Queue::new()).unwrap();
+    /// let submitter = pool.task_submitter(qid).unwrap();
+    /// let (tx,rx) = mpsc::channel();
+    /// let feed = submitter.stream_from(rx, |submitter|{
+    ///     let id = submitter.submit((|line:String|println!("{line}")).into_task()).unwrap();
+    ///     (id,Pi::PI0).into()
+    /// });
+    /// tx.send("hello".to_string()).unwrap();
+    /// drop(tx);
+    /// assert_eq!(feed.join().unwrap(), 1);
+    /// ```
+    pub fn stream_from<T,F>(&self, rx:Receiver<T>, mut slot:F)->JoinHandle<usize>
+        where
+        T: 'static + Debug + Send,
+        F: FnMut(&TaskSubmitter)->CondAddr + Send + 'static,
+    {
+        let submitter = self.clone();
+        thread::spawn(move||{
+            let mut delivered = 0;
+            for value in rx {
+                let ca = slot(&submitter);
+                if let Err(_e) = submitter.deliver(ca, value) {
+                    warn!(task_id=ca.taskid(), pi=ca.pi(); "{_e}, the message is dropped.");
+                    continue;
+                }
+                delivered += 1;
+            }
+            debug!("the channel is disconnected after {delivered} messages delivered.");
+            delivered
+        })
+    }
+
     #[deprecated(
         since="0.3.0",
         note = "Use `submit()` instead for strict type check. \
@@ -202,3 +375,46 @@ fn test_conv() {
     let b = a.downcast_ref::<i64>();
     assert!(b.is_none());
 }
+
+#[test]
+fn test_deliver() {
+    use std::sync::mpsc;
+    use crate::{Pi, Pool, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let (tx,rx) = mpsc::channel();
+    let report = tx.clone();
+    let id = submitter.submit((move|a:i32,b:String|report.send(format!("{b}{a}")).unwrap(), TaskId::from(1)).into_task()).unwrap();
+    assert_eq!(submitter.deliver((id,Pi::PI0).into(), 1), Ok(Delivered::Waiting));
+    // the type mismatched, and a task not waiting
+    assert_eq!(submitter.deliver((id,Pi::PI1).into(), 2), Err(DeliveryError::TypeMismatch {
+        ca: (id,Pi::PI1).into(), expected: "alloc::string::String", actual: "i32" }));
+    assert_eq!(submitter.deliver((TaskId::from(9),Pi::PI0).into(), 2), Err(DeliveryError::NotFound((TaskId::from(9),Pi::PI0).into())));
+    assert_eq!(submitter.deliver((TaskId::NONE,Pi::PI0).into(), 2), Err(DeliveryError::ZeroTaskId((TaskId::NONE,Pi::PI0).into())));
+
+    // a channel bound to a cond, and another to a fresh slot per message
+    let (config_tx,config_rx) = mpsc::channel();
+    let config = submitter.deliver_from(config_rx, (id,Pi::PI1).into());
+    config_tx.send("a".to_string()).unwrap();
+    drop(config_tx);
+    assert_eq!(config.join().unwrap(), 1);
+    let (line_tx,line_rx) = mpsc::channel();
+    let lines = submitter.stream_from(line_rx, move|submitter|{
+        let report = tx.clone();
+        let id = submitter.submit((move|n:i32|report.send(format!("n{n}")).unwrap()).into_task()).unwrap();
+        (id,Pi::PI0).into()
+    });
+    line_tx.send(2).unwrap();
+    line_tx.send(3).unwrap();
+    drop(line_tx);
+    assert_eq!(lines.join().unwrap(), 2);
+
+    pool.spawn_thread_for(qid);
+    let mut received: Vec<String> = rx.iter().take(3).collect();
+    received.sort();
+    assert_eq!(received, ["a1", "n2", "n3"]);
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+}
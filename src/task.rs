@@ -15,14 +15,18 @@ use std::{
     sync::atomic::{AtomicUsize, Ordering},
     ops::{Deref,DerefMut},
     num::NonZeroUsize,
+    fmt::Debug,
+    future::Future,
+    task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
-use crate::{curry::{CallOnce, CallParam, Currier}, meta::TupleOpt};
+use crate::{cancel::Watch, curry::{Awaited, CallMut, CallOnce, CallParam, Currier, Rerun}, meta::TupleOpt};
 use crate::meta::Fndecl;
 
 
 /// Defines the behavior type for tasks.
-#[derive(Clone,Copy)]
+#[derive(Clone,Copy,Debug,PartialEq)]
 pub enum Kind {
     /// Standard task execution.
     /// The thread continues running after task completion.
@@ -80,11 +84,11 @@ pub fn taskid_next()->TaskId {
 pub struct TaskId(pub(crate) Option<NonZeroUsize>);
 
 impl TaskId {
-    const NONE : Self = Self(None);
+    pub(crate) const NONE : Self = Self(None);
 
     #[inline]
     pub fn new(id:usize)->Self {
-        if crate::log::LEVEL as usize >= crate::log::Level::Warn as usize && id == 0 {
+        if crate::log::LEVEL as usize >= crate::log::LogLevel::Warn as usize && id == 0 {
             warn!("TaskId::new() the input id is zero, is not avaiable!");
         }
         Self(NonZeroUsize::new(id))
@@ -204,6 +208,7 @@ impl From<Pi> for u8 {
 ///
 /// This is determined by a combination of the task ID and zero-based condition index,
 /// which together uniquely identify where the parameter is located in the system.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct CondAddr(TaskId,Pi);
 
 impl CondAddr {
@@ -232,17 +237,148 @@ impl From<(TaskId,Pi)> for CondAddr {
 pub(crate) trait Task
 {
     fn run(self:Box<Self>)->Option<Box<dyn Any>>;
+    /// runs without being consumed, only for the tasks built with `retry()`.
+    fn run_mut(&mut self)->Option<Box<dyn Any>>;
+    /// polls the future of the body, only for the tasks built with `awaited()`.
+    fn poll(&mut self, cx:&mut Context<'_>)->Poll<Option<Box<dyn Any>>>;
     fn as_param_mut(&mut self)->Option<&mut dyn CallParam>;
     fn kind(&self)->Kind;
     fn id(&self)->TaskId;
+    fn attr_mut(&mut self)->&mut TaskAttr;
+}
+
+/// The runtime options of a task, configured by the setters of `TaskBuild`.
+#[derive(Default)]
+pub(crate) struct TaskAttr {
+    /// the cond slots pre-filled with a default, but still waiting for a delivery until the timeout.
+    pub(crate) optional: Vec<(Pi,Duration)>,
+    /// the longest time the task waits for its conds.
+    pub(crate) cond_timeout: Option<Duration>,
+    /// where the `CondTimeout` goes when the task is given up.
+    pub(crate) on_cond_timeout: Option<CondAddr>,
+    /// how a failed run is retried.
+    pub(crate) retry: Option<Retry>,
+    /// the longest time a run is expected to take.
+    pub(crate) timeout: Option<Duration>,
+    /// the timeout of an awaited task pending, kept from its first poll.
+    pub(crate) watch: Option<Watch>,
+    /// the named resources and the count of tokens held during a run.
+    pub(crate) requires: Vec<(&'static str,usize)>,
+    /// the class of the task, see `Pool::set_tag_rate_limit()`.
+    pub(crate) tag: Option<&'static str>,
+    /// whether a token of the tag is reserved for the task put aside over the rate.
+    pub(crate) rate_reserved: bool,
+    /// the instant by which the task is expected to start.
+    pub(crate) deadline: Option<Instant>,
+    /// what becomes of the task popped after its deadline.
+    pub(crate) on_deadline_miss: DeadlineMiss,
+    /// whether the task takes the tightest deadline of its downstream chain when released.
+    pub(crate) inherit_deadline: bool,
+    /// the cond which the result goes to, see `to()`.
+    pub(crate) next: Option<CondAddr>,
+    /// whether the result is distributed by `fan_tuple_with()`.
+    pub(crate) fans_out: bool,
+    /// the name the task is registered by in a `TaskRegistry`, see `named()`.
+    pub(crate) name: Option<&'static str>,
+    /// whether the body returns a future polled by the workers, see `awaited()`.
+    pub(crate) awaited: bool,
+    /// whether the result is taken by a `TaskFuture`, so a zero-sized one is not skipped, see `submit_async()`.
+    pub(crate) resolved: bool,
+    /// when the task was added into its queue the last time.
+    #[cfg(feature = "metrics")]
+    pub(crate) queued_at: Option<Instant>,
+}
+
+/// The retry state of a task, see `TaskBuild::retry()`.
+pub(crate) struct Retry {
+    pub(crate) policy: RetryPolicy,
+    /// the count of the failed attempts so far
+    pub(crate) failures: u32,
+    /// tells whether a returned value is a failure, a panic is always a failure.
+    pub(crate) is_failure: Option<Box<IsFailure>>,
+}
+
+pub(crate) type IsFailure = dyn Fn(&dyn Any)->bool + Send;
+
+/// Describes how many times and how late a failed task is run again.
+///
+/// # Example:
+/// ```rust
+/// # use std::time::Duration;
+/// # use taskorch::RetryPolicy;
+/// // at most 5 runs, waiting 10ms, 20ms, 40ms, 50ms between them
+/// let policy = RetryPolicy::exponential(5, Duration::from_millis(10), Duration::from_millis(50));
+/// assert_eq!(policy.backoff(3), Duration::from_millis(40));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// At most `max_attempts` runs (including the first one),
+    /// the delay doubles from `base` after each failure, but never exceeds `max_delay`.
+    pub const fn exponential(max_attempts:u32, base:Duration, max_delay:Duration)->Self {
+        Self { max_attempts, base, max_delay }
+    }
+
+    /// At most `max_attempts` runs (including the first one), with the same `delay` between them.
+    pub const fn fixed(max_attempts:u32, delay:Duration)->Self {
+        Self { max_attempts, base: delay, max_delay: delay }
+    }
+
+    /// The count of runs at most, including the first one.
+    pub const fn max_attempts(&self)->u32 {
+        self.max_attempts
+    }
+
+    /// The delay before the next run, after `failures` (>=1) failed runs.
+    pub fn backoff(&self, failures:u32)->Duration {
+        let factor = 1u32.checked_shl(failures.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// The outcome of a conditional task whose conds were not all delivered within its `cond_timeout()`.
+///
+/// The task is removed from the waiting map and never runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CondTimeout {
+    /// the task given up
+    pub taskid: TaskId,
+    /// the conds still empty at the timeout
+    pub missing: Vec<Pi>,
+}
+
+/// What becomes of a task popped by a worker after its `deadline()`, instead of running.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DeadlineMiss {
+    /// drops the task with a warning.
+    #[default]
+    Drop,
+    /// drops the task and passes a `DeadlineMissed` to the condaddr.
+    Report(CondAddr),
 }
 
+/// The outcome of a task popped after its deadline, see `DeadlineMiss::Report`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadlineMissed {
+    /// the task dropped
+    pub taskid: TaskId,
+    /// the deadline of the task
+    pub deadline: Instant,
+    /// how late the task was popped
+    pub late: Duration,
+}
 
 /// The carrier of the task, used to create and invoke its functionality.
 pub(crate) struct TaskCurrier<Currier> {
     pub(crate) currier: Currier,
     pub(crate) id: TaskId,
     pub(crate) kind: Kind,
+    pub(crate) attr: TaskAttr,
 }
 
 pub(crate) enum TaskMap<MapFn,R> {
@@ -257,13 +393,35 @@ impl<T> Task for TaskCurrier<T>
     T::R: 'static,
 {
     fn run(self:Box<Self>)->Option<Box<dyn Any>> {
+        let resolved = self.attr.resolved;
         let r = self.currier.call_once();
-        if std::mem::size_of::<T::R>() == 0 {
+        if std::mem::size_of::<T::R>() == 0 && !resolved {
+            None
+        } else {
+            Some(Box::new(r))
+        }
+    }
+    fn run_mut(&mut self)->Option<Box<dyn Any>> {
+        let Some(r) = self.currier.call_again() else {
+            unreachable!("task#{:?} is not re-runnable, only those built with retry() are.", self.id);
+        };
+        if std::mem::size_of::<T::R>() == 0 && !self.attr.resolved {
             None
         } else {
             Some(Box::new(r))
         }
     }
+    fn poll(&mut self, cx:&mut Context<'_>)->Poll<Option<Box<dyn Any>>> {
+        let Some(polled) = self.currier.poll(cx) else {
+            unreachable!("task#{:?} is not asynchronous, only those built with awaited() are.", self.id);
+        };
+        let resolved = self.attr.resolved;
+        polled.map(|r|if std::mem::size_of::<T::R>() == 0 && !resolved {
+            None
+        } else {
+            Some(Box::new(r) as Box<dyn Any>)
+        })
+    }
     fn as_param_mut(&mut self)->Option<&mut dyn CallParam> {
         self.currier.as_param_mut()
     }
@@ -273,6 +431,9 @@ impl<T> Task for TaskCurrier<T>
     fn id(&self)->TaskId {
         self.id
     }
+    fn attr_mut(&mut self)->&mut TaskAttr {
+        &mut self.attr
+    }
 }
 
 pub struct TaskBuild<C,MapFn,MapR>(pub(crate) TaskCurrier<C>,pub(crate) TaskMap<MapFn,MapR>);
@@ -284,6 +445,249 @@ impl<C,MapFn,MapR> TaskBuild<C,MapFn,MapR> {
     }
 }
 
+#[allow(private_bounds)]
+impl<C:CallOnce,MapFn,MapR> TaskBuild<C,MapFn,MapR> {
+    /// Pre-fills the cond#`pi` with a default value at build time.
+    ///
+    /// The slot counts as filled, so the task is not waiting for it.
+    /// A value delivered to the slot before the task is scheduled replaces the default.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Pi, TaskBuildNew as _};
+    /// // cond#1 is 0 unless some task passes another value in time
+    /// let task = (|a:i32,b:i32|a+b).into_task().default_cond(Pi::PI1, 0i32);
+    /// ```
+    /// The type of `value` must be identical to the type of the cond, or else it is ignored with an error log.
+    pub fn default_cond<T:'static+Debug>(mut self, pi:Pi, value:T)->Self {
+        let Some(param) = self.0.currier.as_param_mut() else {
+            error!(task_id=self.0.id, pi=pi; "task#{:?} has no cond, the default of cond#{pi:?} is ignored.", self.0.id);
+            return self;
+        };
+        if !param.set(pi.0 as usize, &value) {
+            let _target_type_name = param.typename(pi.0 as usize);
+            let _data_type_name = std::any::type_name::<T>();
+            error!(task_id=self.0.id, pi=pi, type_name=_data_type_name;
+                "task#{:?}.cond#{pi:?} has type <{_target_type_name}> not identical to <{_data_type_name}>, \
+                    the default {{{value:?}}} is ignored.", self.0.id);
+        }
+        self
+    }
+
+    /// Declares the cond#`pi` optional: filled with `value` and waiting for a delivery at most `timeout`.
+    ///
+    /// The task is scheduled once all the required conds are filled and each optional one
+    /// is either delivered or timed out, in which case the task runs with the default value.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use taskorch::{Pi, TaskBuildNew as _};
+    /// // cond#1 is `None` if nothing is delivered within 100ms after submission
+    /// let task = (|a:i32,b:Option<i32>|a+b.unwrap_or(0)).into_task()
+    ///     .optional_cond(Pi::PI1, None::<i32>, Duration::from_millis(100));
+    /// ```
+    pub fn optional_cond<T:'static+Debug>(mut self, pi:Pi, value:T, timeout:Duration)->Self {
+        self = self.default_cond(pi, value);
+        self.0.attr.optional.push((pi,timeout));
+        self
+    }
+
+    /// Gives up the task if its conds are not all delivered within `timeout` after submission.
+    ///
+    /// The task is removed from the waiting map and a `CondTimeout` is produced,
+    /// logged and passed to the handler set by `on_cond_timeout()` if any.
+    /// The task waiting for its result by `to()` is given up as well,
+    /// unless that cond is filled already or optional, then it takes its default.
+    pub fn cond_timeout(mut self, timeout:Duration)->Self {
+        self.0.attr.cond_timeout = Some(timeout);
+        self
+    }
+
+    /// Cancels the task once a run takes longer than `timeout`.
+    ///
+    /// The cancellation is cooperative: the task body polls `CancelToken::current()` and returns early.
+    /// The result of a timed out run is dropped rather than passed to the target condaddrs,
+    /// and a run still going on twice as long as the timeout is reported with its task id.
+    /// The run of a task built with `awaited()` lasts from its first poll,
+    /// and a pending one is dropped once the timeout expires.
+    pub fn timeout(mut self, timeout:Duration)->Self {
+        self.0.attr.timeout = Some(timeout);
+        self
+    }
+
+    /// Holds `n` tokens of the named resource during each run.
+    ///
+    /// The limit of the resource is set by `Pool::set_resource_limit()`.
+    /// A task without enough tokens available is put aside without holding a worker,
+    /// and the tokens are released once the run completes, panics or is cancelled.
+    /// A task requiring more than the limit is refused by `submit()` with `TaskError::OverLimit`,
+    /// or dropped as cancelled when popped if the limit is lowered meanwhile.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{Pool, TaskBuildNew as _};
+    /// let pool = Pool::new();
+    /// pool.set_resource_limit("db", 4);
+    /// let task = (||{/* query the database */}).into_task().requires("db", 1);
+    /// ```
+    pub fn requires(mut self, name:&'static str, n:usize)->Self {
+        self.0.attr.requires.push((name,n));
+        self
+    }
+
+    /// Sets the class of the task, the tasks with the same tag share the rate limit of the tag.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use taskorch::{Pool, TaskBuildNew as _};
+    /// let pool = Pool::new();
+    /// pool.set_tag_rate_limit("api", 10, Duration::from_secs(1));
+    /// let task = (||{/* call the third-party api */}).into_task().tag("api");
+    /// ```
+    pub fn tag(mut self, tag:&'static str)->Self {
+        self.0.attr.tag = Some(tag);
+        self
+    }
+
+    /// Passes the `CondTimeout` to the condaddr `ca` when the task is given up by `cond_timeout()`.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use taskorch::{CondTimeout, Pi, TaskBuildNew as _, TaskId};
+    /// let handler = (|t:CondTimeout|println!("task#{:?} misses {:?}", t.taskid, t.missing), TaskId::from(2)).into_task();
+    /// let task = (|a:i32,b:i32|a+b, TaskId::from(1)).into_task()
+    ///     .cond_timeout(Duration::from_secs(1))
+    ///     .on_cond_timeout((TaskId::from(2),Pi::PI0).into());
+    /// ```
+    pub fn on_cond_timeout(mut self, ca:CondAddr)->Self {
+        self.0.attr.on_cond_timeout = Some(ca);
+        self
+    }
+
+    /// Expects the task to start by the instant `at`.
+    ///
+    /// A queue created by `Queue::edf()` runs the task with the earliest deadline first,
+    /// and a task popped after its deadline does not run, see `on_deadline_miss()`.
+    /// An exit task popped after its deadline does not run either, but its worker exits still.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::{Duration, Instant};
+    /// # use taskorch::{Queue, TaskBuildNew as _};
+    /// let queue = Queue::edf();
+    /// let task = (||{/* decode a frame */}).into_task().deadline(Instant::now() + Duration::from_millis(40));
+    /// ```
+    pub fn deadline(mut self, at:Instant)->Self {
+        self.0.attr.deadline = Some(at);
+        self
+    }
+
+    /// Sets what becomes of the task popped after its deadline, dropped by default.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Instant;
+    /// # use taskorch::{DeadlineMiss, DeadlineMissed, Pi, TaskBuildNew as _, TaskId};
+    /// let handler = (|m:DeadlineMissed|println!("task#{:?} is {:?} late", m.taskid, m.late), TaskId::from(2)).into_task();
+    /// let task = (||{}).into_task()
+    ///     .deadline(Instant::now())
+    ///     .on_deadline_miss(DeadlineMiss::Report((TaskId::from(2),Pi::PI0).into()));
+    /// ```
+    pub fn on_deadline_miss(mut self, policy:DeadlineMiss)->Self {
+        self.0.attr.on_deadline_miss = policy;
+        self
+    }
+
+    /// Takes the tightest deadline of the downstream chain when the conditional task is released.
+    ///
+    /// The chain follows the targets set by `to()` through the tasks still waiting for their conds,
+    /// so an upstream stage is not scheduled later than the stages depending on it.
+    pub fn inherit_deadline(mut self)->Self {
+        self.0.attr.inherit_deadline = true;
+        self
+    }
+
+    /// Names the task after its body registered in a `TaskRegistry`, so that `Pool::checkpoint()` can save it.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{TaskBuildNew as _, TaskId, TaskRegistry};
+    /// let sum = |a:i32,b:i32|a+b;
+    /// let mut registry = TaskRegistry::new();
+    /// registry.register("sum", sum);
+    /// let task = (sum, TaskId::from(1)).into_task().named("sum");
+    /// ```
+    pub fn named(mut self, name:&'static str)->Self {
+        self.0.attr.name = Some(name);
+        self
+    }
+}
+
+#[allow(private_bounds)]
+impl<C:CallMut+RofCurrier,MapFn,MapR> TaskBuild<C,MapFn,MapR> where C::Ret: 'static {
+    /// Runs the task again after a backoff when it panics, following the `policy`.
+    ///
+    /// Each run gets the same conds, so the task must be re-runnable,
+    /// i.e. `FnMut` and all of its cond types are `Clone`.
+    /// The result is passed to the target condaddrs only once, by the final run,
+    /// and a worker is never held while waiting for the next run.
+    ///
+    /// If the final run panics, the panic goes on as if there were no retry.
+    pub fn retry(self, policy:RetryPolicy)->TaskBuild<Rerun<C>,MapFn,MapR> {
+        self.with_retry(Retry { policy, failures: 0, is_failure: None })
+    }
+
+    /// The same as `retry()`, besides, a run is failed too when `is_failure` returns true on its result.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use taskorch::{RetryPolicy, TaskBuildNew as _};
+    /// let task = (||"42".parse::<i32>()).into_task()
+    ///     .retry_if(RetryPolicy::fixed(3, Duration::from_millis(100)), Result::is_err);
+    /// ```
+    pub fn retry_if(self, policy:RetryPolicy, is_failure:fn(&C::Ret)->bool)->TaskBuild<Rerun<C>,MapFn,MapR> {
+        // `Ret` is the very `CallOnce::R`, the type of the boxed result
+        let is_failure = move|r:&dyn Any|r.downcast_ref::<C::Ret>().is_some_and(is_failure);
+        self.with_retry(Retry { policy, failures: 0, is_failure: Some(Box::new(is_failure)) })
+    }
+
+    fn with_retry(self, retry:Retry)->TaskBuild<Rerun<C>,MapFn,MapR> {
+        let TaskBuild(TaskCurrier {currier, id, kind, mut attr}, map) = self;
+        attr.retry = Some(retry);
+        TaskBuild(TaskCurrier { currier: Rerun(currier), id, kind, attr }, map)
+    }
+}
+
+/// the task built by `awaited()`, whose result is the output of the future
+type AwaitedBuild<C> = TaskBuild<Awaited<C,<C as RofCurrier>::Ret>,NullMapFn<<<C as RofCurrier>::Ret as Future>::Output>,()>;
+
+impl<C:RofCurrier,R1> TaskBuild<C,NullMapFn<R1>,()> where C::Ret: Future + Send + 'static {
+    /// Runs the future returned by the body to its output, which is the result of the task.
+    ///
+    /// The workers poll the future, and a pending one is put aside without holding a worker
+    /// until its waker is woken, then added into its queue again to be polled on.
+    /// Each poll counts as a run for the hooks, the events and the metrics, besides the timeout.
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use taskorch::{TaskBuildNew as _, TaskId};
+    /// let task = (|a:i32|async move { a * 2 }, TaskId::from(1)).into_task().awaited();
+    /// ```
+    pub fn awaited(self)->AwaitedBuild<C> {
+        let TaskBuild(TaskCurrier {currier, id, kind, mut attr}, map) = self;
+        attr.awaited = true;
+        let map = match map {
+            TaskMap::To(ca) => TaskMap::To(ca),
+            _ => TaskMap::None,
+        };
+        TaskBuild(TaskCurrier { currier: Awaited::new(currier), id, kind, attr }, map)
+    }
+}
+
 // This is done to prevent exposing `curry` to external users, thereby avoiding unnecessary complexity in the documentation.
 // for the `to()` use the R of CallOnce:R, but it's just visibility inside crate.
 pub trait RofCurrier {
@@ -293,6 +697,12 @@ pub trait RofCurrier {
 impl<F,C:TupleOpt,R> RofCurrier for Currier<F,C,R> {
     type Ret = R;
 }
+impl<C:RofCurrier> RofCurrier for Rerun<C> {
+    type Ret = C::Ret;
+}
+impl<C,Fut:Future> RofCurrier for Awaited<C,Fut> {
+    type Ret = Fut::Output;
+}
 
 impl<Currier:CallOnce+RofCurrier,R1> TaskBuild<Currier, NullMapFn<R1>,()>
 {
@@ -307,6 +717,7 @@ impl<Currier:CallOnce+RofCurrier,R1> TaskBuild<Currier, NullMapFn<R1>,()>
                 currier: self.0.currier,
                 id: self.0.id,
                 kind: self.0.kind,
+                attr: self.0.attr,
             },
             TaskMap::To(ca)
         )
@@ -381,6 +792,7 @@ impl<Currier:CallOnce,MapFn1,R1> TaskBuild<Currier, MapFn1,R1>
                 currier: self.0.currier,
                 id: self.0.id,
                 kind: self.0.kind,
+                attr: self.0.attr,
             },
             TaskMap::ToMany(mapfn, PhantomData),
         )
@@ -543,6 +955,7 @@ impl<F:FnOnce()->R,R> TaskBuildNew<Currier<F,(),R>,NullMapFn<R>,()> for F {
                 currier: Currier::from(self),
                 id: TaskId::NONE,
                 kind: Kind::Normal,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -553,6 +966,7 @@ impl<F:FnOnce()->R,R> TaskBuildNew<Currier<F,(),R>,NullMapFn<R>,()> for F {
                 currier: Currier::from(self),
                 id: TaskId::NONE,
                 kind: Kind::Exit,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -565,6 +979,7 @@ impl<F:FnOnce()->R,R> TaskBuildNew<Currier<F,(),R>,NullMapFn<R>,()> for (F,TaskI
                 currier: Currier::from(self.0),
                 id: self.1,
                 kind: Kind::Normal,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -575,6 +990,7 @@ impl<F:FnOnce()->R,R> TaskBuildNew<Currier<F,(),R>,NullMapFn<R>,()> for (F,TaskI
                 currier: Currier::from(self.0),
                 id: self.1,
                 kind: Kind::Exit,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -588,6 +1004,7 @@ impl<F:FnOnce(P1)->R,P1,R> TaskBuildNew<Currier<F,(P1,),R>,NullMapFn<R>,()> for
                 currier: Currier::from(self),
                 id: TaskId::NONE,
                 kind: Kind::Normal,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -598,6 +1015,7 @@ impl<F:FnOnce(P1)->R,P1,R> TaskBuildNew<Currier<F,(P1,),R>,NullMapFn<R>,()> for
                 currier: Currier::from(self),
                 id: TaskId::NONE,
                 kind: Kind::Exit,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -610,6 +1028,7 @@ impl<F:FnOnce(P1)->R,P1,R> TaskBuildNew<Currier<F,(P1,),R>,NullMapFn<R>,()> for
                 currier: Currier::from(self.0),
                 id: self.1,
                 kind: Kind::Normal,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -620,6 +1039,7 @@ impl<F:FnOnce(P1)->R,P1,R> TaskBuildNew<Currier<F,(P1,),R>,NullMapFn<R>,()> for
                 currier: Currier::from(self.0),
                 id: self.1,
                 kind: Kind::Exit,
+                attr: TaskAttr::default(),
             },
             TaskMap::None
         )
@@ -635,6 +1055,7 @@ macro_rules! impl_task_build_new {
                         currier: Currier::from(self),
                         id: TaskId::NONE,
                         kind: Kind::Normal,
+                        attr: TaskAttr::default(),
                     },
                     TaskMap::None
                 )
@@ -646,6 +1067,7 @@ macro_rules! impl_task_build_new {
                         currier: Currier::from(self),
                         id: TaskId::NONE,
                         kind: Kind::Exit,
+                        attr: TaskAttr::default(),
                     },
                     TaskMap::None
                 )
@@ -660,6 +1082,7 @@ macro_rules! impl_task_build_new {
                         currier: Currier::from(self.0),
                         id: self.1,
                         kind: Kind::Normal,
+                        attr: TaskAttr::default(),
                     },
                     TaskMap::None
                 )
@@ -671,6 +1094,7 @@ macro_rules! impl_task_build_new {
                         currier: Currier::from(self.0),
                         id: self.1,
                         kind: Kind::Exit,
+                        attr: TaskAttr::default(),
                     },
                     TaskMap::None
                 )
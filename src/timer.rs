@@ -0,0 +1,119 @@
+//! ## timer module
+//!
+//! A single background thread shared by the whole crate, firing delayed callbacks.
+//!
+//! The thread is spawned on the first `schedule()` and sleeps until the earliest deadline,
+//! so nothing ever spins or holds a worker while waiting.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct Entry {
+    at: Instant,
+    seq: u64,
+    f: Callback,
+}
+
+// ordered reversely, so the BinaryHeap pops the earliest deadline first
+impl Ord for Entry {
+    fn cmp(&self, other: &Self)->Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self)->Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self)->bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+impl Eq for Entry {}
+
+struct Timer {
+    heap: Mutex<(BinaryHeap<Entry>, u64)>,
+    cond: Condvar,
+}
+
+static TIMER: OnceLock<Timer> = OnceLock::new();
+
+fn timer()->&'static Timer {
+    let mut started = false;
+    let timer = TIMER.get_or_init(|| {
+        started = true;
+        Timer {
+            heap: Mutex::new((BinaryHeap::new(), 0)),
+            cond: Condvar::new(),
+        }
+    });
+    if started {
+        thread::Builder::new()
+            .name("taskorch-timer".into())
+            .spawn(|| timer_loop(TIMER.get().unwrap()))
+            .expect("failed to spawn the timer thread");
+    }
+    timer
+}
+
+fn timer_loop(timer: &Timer) {
+    let mut lock = timer.heap.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        match lock.0.peek() {
+            Some(entry) if entry.at <= now => {
+                let entry = lock.0.pop().unwrap();
+                drop(lock);
+                if catch_unwind(AssertUnwindSafe(entry.f)).is_err() {
+                    error!("a timer callback panicked, ignored.");
+                }
+                lock = timer.heap.lock().unwrap();
+            }
+            Some(entry) => {
+                let wait = entry.at - now;
+                lock = timer.cond.wait_timeout(lock, wait).unwrap().0;
+            }
+            None => {
+                lock = timer.cond.wait(lock).unwrap();
+            }
+        }
+    }
+}
+
+/// Runs `f` on the timer thread once `delay` has elapsed.
+///
+/// The callback should be short, it delays every other callback while running.
+pub(crate) fn schedule(delay: Duration, f: impl FnOnce() + Send + 'static) {
+    let timer = timer();
+    let at = Instant::now() + delay;
+    let mut lock = timer.heap.lock().unwrap();
+    let seq = lock.1;
+    lock.1 += 1;
+    lock.0.push(Entry { at, seq, f: Box::new(f) });
+    drop(lock);
+    timer.cond.notify_one();
+}
+
+#[test]
+fn test_schedule_order() {
+    use std::sync::{mpsc, Arc};
+    let (tx, rx) = mpsc::channel();
+    let tx = Arc::new(Mutex::new(tx));
+    for (i, ms) in [(2, 30), (0, 5), (1, 15)] {
+        let tx = tx.clone();
+        schedule(Duration::from_millis(ms), move || {
+            tx.lock().unwrap().send(i).unwrap();
+        });
+    }
+    let order: Vec<i32> = (0..3).map(|_| rx.recv().unwrap()).collect();
+    assert_eq!(order, [0, 1, 2]);
+}
@@ -0,0 +1,234 @@
+//! ## trace module
+//!
+//! An opt-in recorder of the task runs, the cond deliveries and the releases of the conditional tasks,
+//! written as Chrome `trace_event` JSON to be opened in `chrome://tracing` or Perfetto.
+//!
+//! The recorder is process-wide like the log, and costs an atomic load per event while stopped.
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    io,
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::task::{CondAddr, Kind, TaskId};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+static NEXT_TID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// the numeric id of the current thread in the trace, 0 if not assigned yet
+    static TID: Cell<u64> = const { Cell::new(0) };
+}
+
+struct Recording {
+    start: Instant,
+    events: Vec<Event>,
+    /// the numeric ids and the names of the threads seen
+    threads: Vec<(u64,String)>,
+}
+
+enum Event {
+    Run { taskid:TaskId, qid:usize, kind:Kind, tid:u64, at:Instant, dur:Duration },
+    Deliver { from:TaskId, to:CondAddr, tid:u64, at:Instant },
+    Release { taskid:TaskId, qid:usize, tid:u64, at:Instant },
+}
+
+/// Records the timeline of the task execution, see the module `trace`.
+///
+/// # Example:
+/// ```rust
+/// # use taskorch::TraceRecorder;
+/// TraceRecorder::start();
+/// // submit and run tasks
+/// let trace = TraceRecorder::stop();
+/// std::fs::write(std::env::temp_dir().join("taskorch.json"), trace.to_chrome_json()).unwrap();
+/// ```
+pub struct TraceRecorder;
+
+impl TraceRecorder {
+    /// Starts recording, the events recorded before are discarded.
+    pub fn start() {
+        *RECORDING.lock().unwrap() = Some(Recording {
+            start: Instant::now(),
+            events: Vec::new(),
+            threads: Vec::new(),
+        });
+        ENABLED.store(true, Ordering::Release);
+    }
+
+    /// Stops recording and returns the events recorded since `start()`.
+    pub fn stop()->Trace {
+        ENABLED.store(false, Ordering::Release);
+        let recording = RECORDING.lock().unwrap().take();
+        Trace(recording)
+    }
+
+    pub fn is_recording()->bool {
+        ENABLED.load(Ordering::Acquire)
+    }
+}
+
+fn record(f:impl FnOnce(u64)->Event) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut lock = RECORDING.lock().unwrap();
+    let Some(recording) = lock.as_mut() else {
+        return;
+    };
+    let mut tid = TID.get();
+    if tid == 0 {
+        tid = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+        TID.set(tid);
+    }
+    if !recording.threads.iter().any(|(id,_)|*id == tid) {
+        let thread = thread::current();
+        let name = thread.name().map_or_else(||format!("{:?}", thread.id()), String::from);
+        recording.threads.push((tid,name));
+    }
+    recording.events.push(f(tid));
+}
+
+/// the task has run, `at` is the start and `dur` covers the run and the passing on of its result
+pub(crate) fn ran(taskid:TaskId, qid:usize, kind:Kind, at:Instant, dur:Duration) {
+    record(|tid|Event::Run { taskid, qid, kind, tid, at, dur });
+}
+
+/// a value from the task `from` is delivered to the cond `to`
+pub(crate) fn delivered(from:TaskId, to:CondAddr) {
+    record(|tid|Event::Deliver { from, to, tid, at: Instant::now() });
+}
+
+/// the conditional task has all its conds and is added into the queue
+pub(crate) fn released(taskid:TaskId, qid:usize) {
+    record(|tid|Event::Release { taskid, qid, tid, at: Instant::now() });
+}
+
+/// The events recorded between `TraceRecorder::start()` and `TraceRecorder::stop()`.
+pub struct Trace(Option<Recording>);
+
+fn id_json(taskid:TaskId)->String {
+    taskid.0.map_or(String::from("null"), |id|id.to_string())
+}
+
+/// the name of the slice, `task` for a task without an id
+fn name_of(taskid:TaskId)->String {
+    taskid.0.map_or(String::from("task"), |id|format!("task#{id}"))
+}
+
+/// the string quoted and escaped as a JSON string, the control characters included
+fn quoted(s:&str)->String {
+    let mut out = String::with_capacity(s.len() + 2);
+    crate::log::json_str(&mut out, s);
+    out
+}
+
+impl Trace {
+    /// the count of the events recorded
+    pub fn len(&self)->usize {
+        self.0.as_ref().map_or(0, |r|r.events.len())
+    }
+
+    pub fn is_empty(&self)->bool {
+        self.len() == 0
+    }
+
+    /// Renders the Chrome `trace_event` JSON.
+    ///
+    /// A run is a slice on the track of its thread, a delivery and a release are instants,
+    /// and each delivery is linked by a flow arrow to the next run of the task receiving it.
+    pub fn to_chrome_json(&self)->String {
+        let mut out = String::from("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n");
+        let Some(recording) = &self.0 else {
+            out.push_str("]}\n");
+            return out;
+        };
+        let ts = |at:&Instant|at.saturating_duration_since(recording.start).as_secs_f64() * 1e6;
+        let mut lines = Vec::with_capacity(recording.events.len() + recording.threads.len());
+        for (tid,name) in recording.threads.iter() {
+            lines.push(format!(
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{tid},\"args\":{{\"name\":{}}}}}",
+                quoted(name)));
+        }
+        // the deliveries not run yet, by the receiving task
+        let mut pending: HashMap<usize,VecDeque<usize>> = HashMap::new();
+        for (i,event) in recording.events.iter().enumerate() {
+            let mut line = String::new();
+            match event {
+                Event::Run { taskid, qid, kind, tid, at, dur } => {
+                    let _ = write!(line,
+                        "{{\"name\":\"{}\",\"cat\":\"run\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{tid},\
+                        \"args\":{{\"taskid\":{},\"qid\":{qid},\"kind\":\"{kind:?}\"}}}}",
+                        name_of(*taskid), ts(at), dur.as_secs_f64() * 1e6, id_json(*taskid));
+                    let flows = taskid.0.and_then(|id|pending.remove(&id.get())).unwrap_or_default();
+                    for flow in flows {
+                        let _ = write!(line,
+                            ",\n{{\"name\":\"deliver\",\"cat\":\"cond\",\"ph\":\"f\",\"bp\":\"e\",\"id\":{flow},\
+                            \"ts\":{:.3},\"pid\":1,\"tid\":{tid}}}",
+                            ts(at));
+                    }
+                }
+                Event::Deliver { from, to, tid, at } => {
+                    let _ = write!(line,
+                        "{{\"name\":\"deliver\",\"cat\":\"cond\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{:.3},\"pid\":1,\"tid\":{tid},\
+                        \"args\":{{\"from\":{},\"to\":{},\"cond\":{}}}}},\n\
+                        {{\"name\":\"deliver\",\"cat\":\"cond\",\"ph\":\"s\",\"id\":{i},\"ts\":{:.3},\"pid\":1,\"tid\":{tid}}}",
+                        ts(at), id_json(*from), id_json(to.taskid()), to.pi().0, ts(at));
+                    if let Some(id) = to.taskid().0 {
+                        pending.entry(id.get()).or_default().push_back(i);
+                    }
+                }
+                Event::Release { taskid, qid, tid, at } => {
+                    let _ = write!(line,
+                        "{{\"name\":\"release {}\",\"cat\":\"release\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{:.3},\"pid\":1,\"tid\":{tid},\
+                        \"args\":{{\"taskid\":{},\"qid\":{qid}}}}}",
+                        name_of(*taskid), ts(at), id_json(*taskid));
+                }
+            }
+            lines.push(line);
+        }
+        out.push_str(&lines.join(",\n"));
+        out.push_str("\n]}\n");
+        out
+    }
+
+    /// Writes the Chrome `trace_event` JSON, see `to_chrome_json()`.
+    pub fn write_chrome_json(&self, mut w:impl io::Write)->io::Result<()> {
+        w.write_all(self.to_chrome_json().as_bytes())
+    }
+}
+
+#[test]
+fn test_trace() {
+    use std::sync::mpsc;
+    use crate::{Pi, Pool, Queue, TaskBuildNew};
+
+    let mut pool = Pool::new();
+    let qid = pool.insert_queue(&Queue::new()).unwrap();
+    let submitter = pool.task_submitter(qid).unwrap();
+    let (tx,rx) = mpsc::channel();
+    TraceRecorder::start();
+    let sink = submitter.submit((move|a:i32|tx.send(a).unwrap(), TaskId::from(500)).into_task()).unwrap();
+    submitter.submit((||7).into_task().to((sink,Pi::PI0).into())).unwrap();
+    pool.spawn_thread_for(qid);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(7));
+    submitter.submit((||{}).into_exit_task()).unwrap();
+    pool.join();
+    let trace = TraceRecorder::stop();
+    assert!(!TraceRecorder::is_recording());
+
+    let json = trace.to_chrome_json();
+    assert!(json.starts_with("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n"), "{json}");
+    assert!(json.contains("\"name\":\"task#500\",\"cat\":\"run\",\"ph\":\"X\""), "{json}");
+    assert!(json.contains("\"args\":{\"from\":null,\"to\":500,\"cond\":0}"), "{json}");
+    assert!(json.contains("\"name\":\"release task#500\""), "{json}");
+    assert!(json.contains("\"ph\":\"s\"") && json.contains("\"ph\":\"f\""), "{json}");
+    assert!(json.contains("\"name\":\"thread_name\""), "{json}");
+    assert_eq!(quoted("a\"b\\c\n\t\u{1}"), "\"a\\\"b\\\\c\\n\\t\\u0001\"");
+}
@@ -0,0 +1,171 @@
+//! ## worker module
+//!
+//! How a worker thread is spawned: its name, stack size, cores and OS priority.
+
+use std::{
+    io,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc},
+    thread,
+};
+
+/// The options of the worker threads spawned by `Pool::spawn_thread_for_with()`.
+///
+/// # Example:
+/// ```rust
+/// # use taskorch::WorkerConfig;
+/// // the workers are named decoder-1-0, decoder-1-1, ... for Q#1,
+/// // and run on the cores 2 and 3 with a lower priority.
+/// let cfg = WorkerConfig::new()
+///     .name("decoder-{qid}-{n}")
+///     .stack_size(4 << 20)
+///     .affinity(&[2,3])
+///     .nice(5);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WorkerConfig {
+    name: Option<String>,
+    stack_size: Option<usize>,
+    cores: Vec<usize>,
+    nice: Option<i32>,
+    /// the count of the workers spawned with this config, shared by its clones
+    spawned: Arc<AtomicUsize>,
+}
+
+impl WorkerConfig {
+    pub fn new()->Self {
+        Self::default()
+    }
+
+    /// Names the workers by the pattern, where `{qid}` is replaced by the queue id
+    /// and `{n}` by the count of the workers spawned with this config before.
+    ///
+    /// A named worker shows its name in the log prefix rather than its thread id.
+    pub fn name(mut self, pattern:impl Into<String>)->Self {
+        self.name = Some(pattern.into());
+        self
+    }
+
+    /// The stack size of the workers in bytes, see `std::thread::Builder::stack_size()`.
+    pub fn stack_size(mut self, size:usize)->Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Pins the workers to the cores, each worker may run on any of them.
+    ///
+    /// Only supported on Linux, ignored with a warning elsewhere.
+    pub fn affinity(mut self, cores:&[usize])->Self {
+        self.cores = cores.to_vec();
+        self
+    }
+
+    /// The nice value of the workers, from -20 (the highest priority) to 19 (the lowest).
+    ///
+    /// A negative value usually requires a privilege.
+    /// Only supported on Linux, ignored with a warning elsewhere.
+    pub fn nice(mut self, nice:i32)->Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    /// spawns the thread running `f` after the OS options are applied.
+    pub(crate) fn spawn<F>(&self, qid:Option<usize>, f:F)->io::Result<thread::JoinHandle<()>>
+        where F: FnOnce() + Send + 'static
+    {
+        let n = self.spawned.fetch_add(1, Ordering::Relaxed);
+        let mut builder = thread::Builder::new();
+        if let Some(pattern) = &self.name {
+            let qid = qid.map_or(String::from("?"), |qid|qid.to_string());
+            builder = builder.name(pattern.replace("{qid}", &qid).replace("{n}", &n.to_string()));
+        }
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+        let cores = self.cores.clone();
+        let nice = self.nice;
+        builder.spawn(move||{
+            if !cores.is_empty() && let Err(_err) = os::set_affinity(&cores) {
+                warn!("failed to pin the worker to the cores {cores:?}: {_err}");
+            }
+            if let Some(nice) = nice && let Err(_err) = os::set_nice(nice) {
+                warn!("failed to set the nice of the worker to {nice}: {_err}");
+            }
+            f()
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::{ffi::{c_int, c_uint, c_ulong}, io, mem::size_of};
+
+    /// cpu_set_t of glibc, 1024 cores at most
+    const CPU_SET_WORDS: usize = 1024 / c_ulong::BITS as usize;
+    const PRIO_PROCESS: c_int = 0;
+
+    unsafe extern "C" {
+        fn sched_setaffinity(pid:c_int, cpusetsize:usize, mask:*const c_ulong)->c_int;
+        fn setpriority(which:c_int, who:c_uint, prio:c_int)->c_int;
+    }
+
+    /// pins the calling thread to the cores
+    pub(super) fn set_affinity(cores:&[usize])->io::Result<()> {
+        let bits = c_ulong::BITS as usize;
+        let mut mask = [0 as c_ulong; CPU_SET_WORDS];
+        for core in cores {
+            if *core >= CPU_SET_WORDS * bits {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("core {core} out of range")));
+            }
+            mask[core / bits] |= 1 << (core % bits);
+        }
+        // pid 0 is the calling thread
+        let r = unsafe { sched_setaffinity(0, size_of::<[c_ulong; CPU_SET_WORDS]>(), mask.as_ptr()) };
+        if r == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    /// sets the nice of the calling thread, on Linux the nice is per thread rather than per process
+    pub(super) fn set_nice(nice:i32)->io::Result<()> {
+        let r = unsafe { setpriority(PRIO_PROCESS, 0, nice) };
+        if r == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os {
+    use std::io;
+
+    pub(super) fn set_affinity(_cores:&[usize])->io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "core affinity is only supported on Linux"))
+    }
+
+    pub(super) fn set_nice(_nice:i32)->io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "nice is only supported on Linux"))
+    }
+}
+
+#[test]
+fn test_worker_config() {
+    let cfg = WorkerConfig::new().name("w-{qid}-{n}").stack_size(256 << 10);
+    let names: Vec<String> = (0..2).map(|_|{
+        cfg.clone()
+            .spawn(Some(7), ||{})
+            .unwrap()
+            .thread()
+            .name()
+            .unwrap()
+            .to_string()
+    }).collect();
+    assert_eq!(names, ["w-7-0", "w-7-1"]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_affinity() {
+    let handle = WorkerConfig::new().affinity(&[0]).nice(1)
+        .spawn(None, ||{
+            assert!(os::set_affinity(&[0]).is_ok());
+            assert!(os::set_affinity(&[4096]).is_err());
+        })
+        .unwrap();
+    handle.join().unwrap();
+}